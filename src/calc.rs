@@ -0,0 +1,625 @@
+//! The damage and defense formulas of the system.
+//!
+//! Accuracy is given in points as written on the character sheet and mana in
+//! points spent on the cast. Every number the formulas read comes from the
+//! magic's [`Origin`].
+
+use evalexpr::{EvalexprResult, Value};
+
+use crate::magic::MagicRank;
+use crate::rules::{AccuracyCurve, DamageFormula, DefenseFormula, Origin};
+
+/// Damage per point of mana for a rank and origin, before accuracy and race
+/// are applied.
+#[inline]
+pub fn get_multiplier(origin: &Origin, rank: MagicRank) -> f64 {
+    origin.multiplier(rank)
+}
+
+/// Raw damage of spending `mana`. The multiplier is rounded down first, so
+/// e.g. a Common CHAOS magic deals 5 per mana, not 5.5.
+#[inline]
+pub fn calculate_total_damage(origin: &Origin, mana: i64, rank: MagicRank) -> f64 {
+    (mana as f64) * get_multiplier(origin, rank).floor()
+}
+
+fn accuracy_on_curve(curve: &AccuracyCurve, accr: i64) -> EvalexprResult<f64> {
+    let t: f64 = accr as f64;
+    Ok(match curve {
+        AccuracyCurve::Linear { base, slope } => base + t * slope,
+        AccuracyCurve::Logistic {
+            min,
+            max,
+            midpoint,
+            steepness,
+        } => min + (max - min) / (1.0 + (-steepness * (t - midpoint)).exp()),
+        AccuracyCurve::Steps(steps) => steps
+            .iter()
+            .rev()
+            .find(|&&(from, _)| from <= accr)
+            .or(steps.first())
+            .map_or(0.0, |&(_, factor)| factor),
+        AccuracyCurve::Parity { even, odd } => {
+            if accr % 2 == 0 {
+                accuracy_on_curve(even, accr)?
+            } else {
+                accuracy_on_curve(odd, accr)?
+            }
+        }
+        AccuracyCurve::Capped { max, curve } => accuracy_on_curve(curve, accr)?.min(*max),
+        AccuracyCurve::Expression(formula) => formula.eval(&[("accuracy", Value::Int(accr))])?,
+    })
+}
+
+/// The factor applied to the raw damage for `accr` points of accuracy,
+/// following the origin's accuracy curve. By default CHAOS grows slower on
+/// odd accuracy values. Fails only if the curve is a formula that fails.
+#[inline]
+pub fn calculate_accuracy(origin: &Origin, accr: i64) -> EvalexprResult<f64> {
+    accuracy_on_curve(&origin.accuracy, accr)
+}
+
+/// Whether `accr` is an odd accuracy that the origin's `parity` curve makes
+/// worth less than the even curve would, like CHAOS's odd accuracies.
+pub fn has_parity_penalty(origin: &Origin, accr: i64) -> bool {
+    fn penalized(curve: &AccuracyCurve, accr: i64) -> bool {
+        match curve {
+            AccuracyCurve::Parity { even, odd } => {
+                accr % 2 != 0
+                    && matches!(
+                        (accuracy_on_curve(odd, accr), accuracy_on_curve(even, accr)),
+                        (Ok(odd), Ok(even)) if odd < even
+                    )
+            }
+            AccuracyCurve::Capped { curve, .. } => penalized(curve, accr),
+            _ => false,
+        }
+    }
+    penalized(&origin.accuracy, accr)
+}
+
+/// Damage actually dealt: raw damage times accuracy and the race multiplier
+/// `mul`, rounded down, unless the origin has its own damage formula. Fails
+/// if one of the origin's formulas fails.
+#[inline]
+pub fn calculate_effective_damage(
+    origin: &Origin,
+    accr: i64,
+    mana: i64,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<i64> {
+    Ok(calculate_effective_damage_f64(origin, accr, mana, rank, mul)? as i64)
+}
+
+#[inline]
+fn calculate_effective_damage_f64(
+    origin: &Origin,
+    accr: i64,
+    mana: i64,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<f64> {
+    let accuracy = calculate_accuracy(origin, accr)?;
+    match &origin.damage {
+        DamageFormula::Standard => {
+            Ok((calculate_total_damage(origin, mana, rank) * accuracy * mul).floor())
+        }
+        DamageFormula::Expression(formula) => formula.eval(&[
+            ("mana", Value::Int(mana)),
+            ("accuracy", Value::Float(accuracy)),
+            ("rank_mult", Value::Float(get_multiplier(origin, rank))),
+            ("race_mult", Value::Float(mul)),
+        ]),
+    }
+}
+
+/// Life of the barrier raised by casting defensively, following the origin's
+/// defense formula. ORDER gets 1.3 times the effective damage; CHAOS loses
+/// the logarithm of it on top of that, and gets no barrier from a cast that
+/// deals no damage. Fails if one of the origin's formulas fails.
+#[inline]
+pub fn calculate_defense_life(
+    origin: &Origin,
+    accr: i64,
+    mana: i64,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<i64> {
+    let r = calculate_effective_damage_f64(origin, accr, mana, rank, mul)?;
+    Ok(match &origin.defense {
+        DefenseFormula::Scaled { factor } => (factor * r) as i64,
+        DefenseFormula::ScaledLog { .. } if r <= 0.0 => 0,
+        DefenseFormula::ScaledLog { factor } => ((factor * r) - r.ln()) as i64,
+        DefenseFormula::Expression(formula) => formula.eval(&[
+            ("mana", Value::Int(mana)),
+            ("accuracy", Value::Float(calculate_accuracy(origin, accr)?)),
+            ("rank_mult", Value::Float(get_multiplier(origin, rank))),
+            ("race_mult", Value::Float(mul)),
+            ("damage", Value::Float(r)),
+        ])? as i64,
+    })
+}
+
+/// The most mana the solvers will try before calling a target unreachable.
+pub const MAX_MANA: i64 = 1_000_000;
+
+/// The least mana for which `formula` reaches `target`, assuming more mana
+/// never gives less. Probes doubling amounts of mana and then bisects, so it
+/// checks the rounded results of the formula itself rather than inverting it.
+fn mana_needed<F: Fn(i64) -> EvalexprResult<i64>>(
+    target: i64,
+    formula: F,
+) -> EvalexprResult<Option<i64>> {
+    if target <= 0 {
+        return Ok(Some(0));
+    }
+
+    let mut low = 0;
+    let mut high = 1;
+    while formula(high)? < target {
+        if high == MAX_MANA {
+            return Ok(None);
+        }
+        low = high;
+        high = (high * 2).min(MAX_MANA);
+    }
+
+    // formula(low) misses the target and formula(high) reaches it.
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if formula(middle)? < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(Some(high))
+}
+
+/// The least mana whose [`calculate_effective_damage`] is at least `target`,
+/// or `None` if not even [`MAX_MANA`] reaches it.
+pub fn calculate_mana_for_damage(
+    origin: &Origin,
+    target: i64,
+    accr: i64,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<Option<i64>> {
+    mana_needed(target, |mana| {
+        calculate_effective_damage(origin, accr, mana, rank, mul)
+    })
+}
+
+/// The least mana whose [`calculate_defense_life`] is at least `target`, or
+/// `None` if not even [`MAX_MANA`] reaches it.
+pub fn calculate_mana_for_defense(
+    origin: &Origin,
+    target: i64,
+    accr: i64,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<Option<i64>> {
+    mana_needed(target, |mana| {
+        calculate_defense_life(origin, accr, mana, rank, mul)
+    })
+}
+
+/// One way of splitting a budget between accuracy and mana.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Split {
+    pub accuracy: i64,
+    pub mana: i64,
+    /// Damage or barrier life of the cast.
+    pub value: i64,
+}
+
+/// The most accuracy the split solvers will try.
+pub const MAX_ACCURACY: i64 = 10_000;
+
+/// The best `count` splits of `budget` where each accuracy point costs
+/// `cost_per_accuracy` and the rest, rounded down, goes to mana, trying up
+/// to [`MAX_ACCURACY`]. Best first; ties go to the split with less accuracy.
+fn splits<F: Fn(i64, i64) -> EvalexprResult<i64>>(
+    budget: i64,
+    cost_per_accuracy: f64,
+    count: usize,
+    formula: F,
+) -> EvalexprResult<Vec<Split>> {
+    let mut best: Vec<Split> = Vec::with_capacity(count + 1);
+    for accuracy in 0..=MAX_ACCURACY {
+        let mana = (budget as f64 - accuracy as f64 * cost_per_accuracy).floor();
+        if mana < 0.0 {
+            break;
+        }
+        let split = Split {
+            accuracy,
+            mana: mana as i64,
+            value: formula(accuracy, mana as i64)?,
+        };
+        // After every split of equal value, which all have less accuracy.
+        let place = best.partition_point(|s| s.value >= split.value);
+        if place < count {
+            best.insert(place, split);
+            best.truncate(count);
+        }
+    }
+    Ok(best)
+}
+
+/// The best `count` splits of `budget` between accuracy and mana ranked by
+/// [`calculate_effective_damage`]. Every accuracy up to [`MAX_ACCURACY`] is
+/// tried, so the slower odd accuracies of CHAOS rank where they belong.
+/// `cost_per_accuracy` must be positive.
+pub fn calculate_damage_splits(
+    origin: &Origin,
+    budget: i64,
+    cost_per_accuracy: f64,
+    count: usize,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<Vec<Split>> {
+    splits(budget, cost_per_accuracy, count, |accr, mana| {
+        calculate_effective_damage(origin, accr, mana, rank, mul)
+    })
+}
+
+/// The best `count` splits of `budget` between accuracy and mana ranked by
+/// [`calculate_defense_life`]. `cost_per_accuracy` must be positive.
+pub fn calculate_defense_splits(
+    origin: &Origin,
+    budget: i64,
+    cost_per_accuracy: f64,
+    count: usize,
+    rank: MagicRank,
+    mul: f64,
+) -> EvalexprResult<Vec<Split>> {
+    splits(budget, cost_per_accuracy, count, |accr, mana| {
+        calculate_defense_life(origin, accr, mana, rank, mul)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MagicType;
+    use crate::rules::{Formula, Ruleset, ACCURACY_VARIABLES, DAMAGE_VARIABLES, DEFENSE_VARIABLES};
+    use evalexpr::EvalexprError;
+
+    const RANKS: [MagicRank; 3] = [MagicRank::Common, MagicRank::Epic, MagicRank::Divine];
+
+    /// `(accuracy, mana)` pairs checked against every rank in `RANKS`.
+    const CASTS: [(i64, i64); 4] = [(10, 10), (7, 25), (0, 1), (13, 3)];
+
+    type Calculation = fn(&Origin, i64, i64, MagicRank, f64) -> EvalexprResult<i64>;
+
+    fn origin(typ: MagicType) -> Origin {
+        Ruleset::default().origin(&typ).unwrap().clone()
+    }
+
+    fn pinned(typ: MagicType, f: Calculation) -> Vec<i64> {
+        let origin = origin(typ);
+        RANKS
+            .iter()
+            .flat_map(|&rank| {
+                CASTS
+                    .iter()
+                    .map(|&(a, m)| f(&origin, a, m, rank, 1.2).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multipliers() {
+        let order = origin(MagicType::ORDER);
+        let chaos = origin(MagicType::CHAOS);
+        assert_eq!(get_multiplier(&order, MagicRank::Common), 4.0);
+        assert_eq!(get_multiplier(&chaos, MagicRank::Common), 5.5);
+        assert_eq!(get_multiplier(&order, MagicRank::Legendary), 13.0);
+        assert_eq!(get_multiplier(&chaos, MagicRank::Divine), 25.5);
+    }
+
+    #[test]
+    fn total_damage_floors_the_multiplier() {
+        assert_eq!(
+            calculate_total_damage(&origin(MagicType::CHAOS), 10, MagicRank::Common),
+            50.0
+        );
+        assert_eq!(
+            calculate_total_damage(&origin(MagicType::ORDER), 10, MagicRank::Uncommon),
+            60.0
+        );
+    }
+
+    #[test]
+    fn total_damage_reads_the_origin() {
+        let mut order = origin(MagicType::ORDER);
+        order.multipliers[MagicRank::Common.index()] = 7.0;
+        assert_eq!(calculate_total_damage(&order, 10, MagicRank::Common), 70.0);
+    }
+
+    #[test]
+    fn accuracy() {
+        let order = origin(MagicType::ORDER);
+        let chaos = origin(MagicType::CHAOS);
+        assert_eq!(calculate_accuracy(&order, 10), Ok(0.75));
+        assert_eq!(calculate_accuracy(&chaos, 10), Ok(0.75));
+        assert_eq!(calculate_accuracy(&order, 11), Ok(0.775));
+        assert_eq!(calculate_accuracy(&chaos, 11), Ok(0.7475));
+        assert_eq!(calculate_accuracy(&chaos, 0), Ok(0.5));
+    }
+
+    #[test]
+    fn parity_penalty() {
+        let order = origin(MagicType::ORDER);
+        let chaos = origin(MagicType::CHAOS);
+        assert!(has_parity_penalty(&chaos, 11));
+        assert!(!has_parity_penalty(&chaos, 12));
+        assert!(!has_parity_penalty(&chaos, -3));
+        assert!(!has_parity_penalty(&order, 11));
+    }
+
+    #[test]
+    fn accuracy_curves() {
+        let mut origin = origin(MagicType::ORDER);
+
+        origin.accuracy = AccuracyCurve::Capped {
+            max: 0.8,
+            curve: Box::default(),
+        };
+        assert_eq!(calculate_accuracy(&origin, 10), Ok(0.75));
+        assert_eq!(calculate_accuracy(&origin, 20), Ok(0.8));
+
+        origin.accuracy = AccuracyCurve::Logistic {
+            min: 0.5,
+            max: 1.5,
+            midpoint: 10.0,
+            steepness: 0.5,
+        };
+        assert_eq!(calculate_accuracy(&origin, 10), Ok(1.0));
+        assert!(calculate_accuracy(&origin, 0).unwrap() > 0.5);
+        assert!(calculate_accuracy(&origin, 40).unwrap() < 1.5);
+
+        origin.accuracy = AccuracyCurve::Steps(vec![(5, 0.6), (10, 0.8), (15, 1.0)]);
+        assert_eq!(calculate_accuracy(&origin, 0), Ok(0.6));
+        assert_eq!(calculate_accuracy(&origin, 9), Ok(0.6));
+        assert_eq!(calculate_accuracy(&origin, 10), Ok(0.8));
+        assert_eq!(calculate_accuracy(&origin, 99), Ok(1.0));
+    }
+
+    #[test]
+    fn formulas() {
+        let standard = origin(MagicType::CHAOS);
+        let mut written = standard.clone();
+        written.accuracy = AccuracyCurve::Expression(
+            Formula::new(
+                "if(accuracy % 2 == 0, 0.5 + accuracy * 0.025, 0.5 + accuracy * 0.0225)",
+                ACCURACY_VARIABLES,
+            )
+            .unwrap(),
+        );
+        written.damage = DamageFormula::Expression(
+            Formula::new(
+                "floor(mana * floor(rank_mult) * accuracy * race_mult)",
+                DAMAGE_VARIABLES,
+            )
+            .unwrap(),
+        );
+        written.defense = DefenseFormula::Expression(
+            Formula::new("1.3 * damage - math::ln(damage)", DEFENSE_VARIABLES).unwrap(),
+        );
+
+        for &rank in &RANKS {
+            for &(a, m) in &CASTS {
+                assert_eq!(
+                    calculate_effective_damage(&written, a, m, rank, 1.2),
+                    calculate_effective_damage(&standard, a, m, rank, 1.2)
+                );
+                assert_eq!(
+                    calculate_defense_life(&written, a, m, rank, 1.2),
+                    calculate_defense_life(&standard, a, m, rank, 1.2)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn failing_formulas_are_errors() {
+        let mut origin = origin(MagicType::ORDER);
+        origin.damage = DamageFormula::Expression(
+            Formula::new("if(mana > 50, \"lots\", mana)", DAMAGE_VARIABLES).unwrap(),
+        );
+        assert_eq!(
+            calculate_effective_damage(&origin, 10, 10, MagicRank::Common, 1.0),
+            Ok(10)
+        );
+        assert!(calculate_effective_damage(&origin, 10, 60, MagicRank::Common, 1.0).is_err());
+        assert!(calculate_mana_for_damage(&origin, 100, 10, MagicRank::Common, 1.0).is_err());
+
+        origin.damage = DamageFormula::Expression(
+            Formula::new("mana / (accuracy - 0.75)", DAMAGE_VARIABLES).unwrap(),
+        );
+        assert_eq!(
+            calculate_defense_life(&origin, 10, 3, MagicRank::Common, 1.0),
+            Err(EvalexprError::CustomMessage(
+                "the formula `mana / (accuracy - 0.75)` gives inf for mana = 3, accuracy = 0.75, \
+             rank_mult = 4, race_mult = 1"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn order_damage() {
+        assert_eq!(
+            pinned(MagicType::ORDER, calculate_effective_damage),
+            [36, 81, 2, 11, 81, 182, 5, 26, 216, 486, 14, 71]
+        );
+    }
+
+    #[test]
+    fn chaos_damage() {
+        assert_eq!(
+            pinned(MagicType::CHAOS, calculate_effective_damage),
+            [45, 98, 3, 14, 90, 197, 6, 28, 225, 493, 15, 71]
+        );
+    }
+
+    #[test]
+    fn order_defense() {
+        assert_eq!(
+            pinned(MagicType::ORDER, calculate_defense_life),
+            [46, 105, 2, 14, 105, 236, 6, 33, 280, 631, 18, 92]
+        );
+    }
+
+    #[test]
+    fn chaos_defense() {
+        assert_eq!(
+            pinned(MagicType::CHAOS, calculate_defense_life),
+            [54, 122, 2, 15, 112, 250, 6, 33, 287, 634, 16, 88]
+        );
+    }
+
+    #[test]
+    fn mana_needed_is_the_least_that_reaches_the_target() {
+        type Solver = fn(&Origin, i64, i64, MagicRank, f64) -> EvalexprResult<Option<i64>>;
+        let pairs: [(Solver, Calculation); 2] = [
+            (calculate_mana_for_damage, calculate_effective_damage),
+            (calculate_mana_for_defense, calculate_defense_life),
+        ];
+
+        for typ in [MagicType::ORDER, MagicType::CHAOS] {
+            let origin = origin(typ);
+            for (solve, formula) in pairs {
+                for target in [1, 7, 120, 1000] {
+                    for accuracy in [0, 13, 14] {
+                        let mana = solve(&origin, target, accuracy, MagicRank::Epic, 1.2)
+                            .unwrap()
+                            .unwrap();
+                        assert!(
+                            formula(&origin, accuracy, mana, MagicRank::Epic, 1.2).unwrap()
+                                >= target
+                        );
+                        assert!(
+                            mana == 1
+                                || formula(&origin, accuracy, mana - 1, MagicRank::Epic, 1.2)
+                                    .unwrap()
+                                    < target
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chaos_casts_without_damage_raise_no_barrier() {
+        let chaos = origin(MagicType::CHAOS);
+        assert_eq!(
+            calculate_effective_damage(&chaos, -19, 1, MagicRank::Common, 1.0),
+            Ok(0)
+        );
+        assert_eq!(
+            calculate_defense_life(&chaos, -19, 1, MagicRank::Common, 1.0),
+            Ok(0)
+        );
+        assert_eq!(
+            calculate_defense_life(&chaos, 10, 0, MagicRank::Common, 1.0),
+            Ok(0)
+        );
+
+        let mana = calculate_mana_for_defense(&chaos, 50, -19, MagicRank::Common, 1.0)
+            .unwrap()
+            .unwrap();
+        assert!(calculate_defense_life(&chaos, -19, mana, MagicRank::Common, 1.0).unwrap() >= 50);
+        assert!(
+            calculate_defense_life(&chaos, -19, mana - 1, MagicRank::Common, 1.0).unwrap() < 50
+        );
+    }
+
+    #[test]
+    fn unreachable_targets() {
+        let order = origin(MagicType::ORDER);
+        assert_eq!(
+            calculate_mana_for_damage(&order, 100, -20, MagicRank::Common, 1.0),
+            Ok(None)
+        );
+        assert_eq!(
+            calculate_mana_for_damage(&order, 0, -20, MagicRank::Common, 1.0),
+            Ok(Some(0))
+        );
+        assert_eq!(
+            calculate_mana_for_damage(&order, 120, 14, MagicRank::Common, 1.0),
+            Ok(Some(36))
+        );
+    }
+
+    #[test]
+    fn splits_rank_every_accuracy() {
+        let order = origin(MagicType::ORDER);
+        let splits = calculate_damage_splits(&order, 40, 1.0, 100, MagicRank::Common, 1.0).unwrap();
+        assert_eq!(splits.len(), 41);
+        // 4 * (40 - a) * (0.5 + a * 0.025) peaks at a = 10.
+        assert_eq!(
+            splits[0],
+            Split {
+                accuracy: 10,
+                mana: 30,
+                value: 90
+            }
+        );
+
+        let splits = calculate_damage_splits(&order, 10, 2.5, 100, MagicRank::Common, 1.0).unwrap();
+        assert_eq!(
+            splits.iter().map(|s| s.mana).collect::<Vec<_>>(),
+            [10, 7, 5, 2, 0]
+        );
+    }
+
+    #[test]
+    fn splits_respect_the_chaos_parity() {
+        let chaos = origin(MagicType::CHAOS);
+        let splits = calculate_damage_splits(&chaos, 41, 1.0, 100, MagicRank::Common, 1.0).unwrap();
+        let odd = splits.iter().find(|s| s.accuracy == 11).unwrap();
+        let even = splits.iter().find(|s| s.accuracy == 12).unwrap();
+        assert_eq!(
+            odd.value,
+            calculate_effective_damage(&chaos, 11, 30, MagicRank::Common, 1.0).unwrap()
+        );
+        assert!(odd.value < even.value);
+        assert_eq!(splits[0].accuracy % 2, 0);
+    }
+
+    #[test]
+    fn splits_keep_only_the_best() {
+        let order = origin(MagicType::ORDER);
+        let all = calculate_damage_splits(&order, 40, 1.0, 100, MagicRank::Common, 1.0).unwrap();
+        let best = calculate_damage_splits(&order, 40, 1.0, 5, MagicRank::Common, 1.0).unwrap();
+        assert_eq!(best, all[..5]);
+
+        // Huge budgets and tiny costs stop at MAX_ACCURACY.
+        for (budget, cost) in [(100_000_000, 1.0), (100, 1e-9)] {
+            let best =
+                calculate_damage_splits(&order, budget, cost, 5, MagicRank::Common, 1.0).unwrap();
+            assert_eq!(best.len(), 5);
+            assert!(best.iter().all(|s| s.accuracy <= MAX_ACCURACY));
+        }
+    }
+
+    #[test]
+    fn chaos_barriers_need_mana() {
+        let chaos = origin(MagicType::CHAOS);
+        let best = calculate_defense_splits(&chaos, 20, 1.0, 5, MagicRank::Common, 1.0).unwrap();
+        assert!(best.iter().all(|s| s.mana > 0));
+        assert_eq!(
+            best[0],
+            Split {
+                accuracy: 0,
+                mana: 20,
+                value: 61
+            }
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+
+use rpgcalc::table::TableFormat;
+
+pub const USAGE: &str = "Usage: rpgcalc [OPTIONS]
+       rpgcalc [OPTIONS] run <script>
+
+Commands:
+  run <script>        Evaluate every line of <script> and exit. Use `-` to
+                      read the script from standard input. Piping into
+                      rpgcalc without a command does the same.
+
+Options:
+  -i, --init <path>   Load magics from <path>. Can be given more than once.
+                      Defaults to init.rpg in the current directory.
+  -r, --rules <path>  Load rules from <path> before any init file. Can be
+                      given more than once.
+  -e, --eval <expr>   Evaluate <expr>, print the result and exit.
+                      Can be given more than once.
+  -f, --format <name> Print tables as terminal, csv, json, markdown or
+                      html. Defaults to terminal.
+  -w, --watch         Load the init and rules files again whenever they
+                      change while the calculator is open.
+  -s, --session <path>
+                      Load the variables saved in <path> when the
+                      calculator opens, and save them there when it closes.
+      --no-color      Don't style the output.
+  -q, --quiet         Only print results and errors.
+  -h, --help          Print this help and exit.
+  -V, --version       Print the version and exit.";
+
+pub const DEFAULT_INIT_FILE: &str = "init.rpg";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub init_files: Vec<String>,
+    pub rules_files: Vec<String>,
+    pub eval: Vec<String>,
+    /// Script given with `run <script>`; `-` means standard input.
+    pub script: Option<String>,
+    pub table_format: TableFormat,
+    pub watch: bool,
+    /// Loaded when the REPL starts and saved when it ends.
+    pub session: Option<String>,
+    pub color: bool,
+    pub quiet: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            init_files: Vec::new(),
+            rules_files: Vec::new(),
+            eval: Vec::new(),
+            script: None,
+            table_format: TableFormat::Terminal,
+            watch: false,
+            session: None,
+            color: true,
+            quiet: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgError {
+    MissingValue(String),
+    UnknownArgument(String),
+    InvalidValue { flag: String, value: String },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgError::MissingValue(flag) => write!(f, "`{}` needs a value", flag),
+            ArgError::UnknownArgument(arg) => write!(f, "unknown argument `{}`", arg),
+            ArgError::InvalidValue { flag, value } => {
+                write!(f, "`{}` is not a valid value for `{}`", value, flag)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// Parses the command line, without the program name. Both `--flag value`
+/// and `--flag=value` are accepted for flags that take a value.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ArgError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || match inline_value {
+            Some(v) => Ok(v.to_string()),
+            None => args
+                .next()
+                .ok_or_else(|| ArgError::MissingValue(flag.clone())),
+        };
+
+        match flag.as_str() {
+            "-i" | "--init" => options.init_files.push(value()?),
+            "-r" | "--rules" => options.rules_files.push(value()?),
+            "-e" | "--eval" => options.eval.push(value()?),
+            "-f" | "--format" => {
+                let name = value()?;
+                options.table_format = TableFormat::from_name(&name)
+                    .ok_or(ArgError::InvalidValue { flag, value: name })?;
+            }
+            "-w" | "--watch" => options.watch = true,
+            "-s" | "--session" => options.session = Some(value()?),
+            "--no-color" => options.color = false,
+            "-q" | "--quiet" => options.quiet = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "run" if options.script.is_none() => options.script = Some(value()?),
+            _ => return Err(ArgError::UnknownArgument(arg)),
+        }
+    }
+
+    if options.init_files.is_empty() {
+        options.init_files.push(DEFAULT_INIT_FILE.to_string());
+    }
+
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, ArgError> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn defaults_to_init_rpg() {
+        assert_eq!(
+            parse(&[]),
+            Ok(Command::Run(Options {
+                init_files: vec!["init.rpg".to_string()],
+                ..Options::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn repeated_and_inline_values() {
+        assert_eq!(
+            parse(&[
+                "--init",
+                "campaign.rpg",
+                "--init=ana.rpg",
+                "--rules",
+                "house.rpg",
+                "-e",
+                "fire(10, 10)",
+                "--no-color",
+                "-q",
+                "--format=csv",
+                "--watch",
+                "--session=ana.calc"
+            ]),
+            Ok(Command::Run(Options {
+                init_files: vec!["campaign.rpg".to_string(), "ana.rpg".to_string()],
+                rules_files: vec!["house.rpg".to_string()],
+                eval: vec!["fire(10, 10)".to_string()],
+                script: None,
+                table_format: TableFormat::Csv,
+                watch: true,
+                session: Some("ana.calc".to_string()),
+                color: false,
+                quiet: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert_eq!(
+            parse(&["--init"]),
+            Err(ArgError::MissingValue("--init".to_string()))
+        );
+        assert_eq!(
+            parse(&["--colour"]),
+            Err(ArgError::UnknownArgument("--colour".to_string()))
+        );
+        assert_eq!(
+            parse(&["-f", "xml"]),
+            Err(ArgError::InvalidValue {
+                flag: "-f".to_string(),
+                value: "xml".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn run_command() {
+        assert_eq!(
+            parse(&["run", "prep.calc", "--init", "ana.rpg"]),
+            Ok(Command::Run(Options {
+                init_files: vec!["ana.rpg".to_string()],
+                script: Some("prep.calc".to_string()),
+                ..Options::default()
+            }))
+        );
+        assert_eq!(
+            parse(&["run"]),
+            Err(ArgError::MissingValue("run".to_string()))
+        );
+    }
+}
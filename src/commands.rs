@@ -0,0 +1,142 @@
+use evalexpr::{HashMapContext, IterateVariablesContext};
+use rpgcalc::table::Table;
+use rpgcalc::Magic;
+
+pub const HELP: &str = "Commands:
+  :help               Show this help.
+  :list               List the loaded magics.
+  :vars               List your variables.
+  :clear              Forget your variables.
+  :reload             Load the init and rules files again.
+  :save <file>        Save your variables and what was evaluated to <file>.
+  :load <file>        Replace your variables with those saved in <file>.
+                      Without a file, both use the --session file.
+  :quit               Leave the calculator. Ctrl-D does the same.
+
+Anything else is evaluated. For a magic called fire, try fire(), t_fire(),
+grid_fire(), need_fire(), best_fire() or plot_fire() to see how to use them,
+or press Tab to complete a name.";
+
+/// A REPL command, written with a leading colon, e.g. `:list`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetaCommand {
+    Help,
+    List,
+    Vars,
+    Clear,
+    Reload,
+    Save(Option<String>),
+    Load(Option<String>),
+    Quit,
+}
+
+impl MetaCommand {
+    /// The command on `line`, or `None` if the line doesn't start with a
+    /// colon and should be evaluated instead.
+    pub fn parse(line: &str) -> Option<Result<Self, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim().to_string())),
+            None => (line, None),
+        };
+        let argument = argument.filter(|a| !a.is_empty());
+        let command = match name {
+            "help" | "h" | "?" => MetaCommand::Help,
+            "list" | "l" => MetaCommand::List,
+            "vars" | "v" => MetaCommand::Vars,
+            "clear" => MetaCommand::Clear,
+            "reload" | "r" => MetaCommand::Reload,
+            "save" => return Some(Ok(MetaCommand::Save(argument))),
+            "load" => return Some(Ok(MetaCommand::Load(argument))),
+            "quit" | "q" | "exit" => MetaCommand::Quit,
+            _ => return Some(Err(format!("unknown command `:{}`, see `:help`", name))),
+        };
+        Some(match argument {
+            Some(_) => Err(format!("`:{}` doesn't take an argument", name)),
+            None => Ok(command),
+        })
+    }
+}
+
+/// The loaded magics with the properties from their `register` blocks.
+pub fn magic_table(magics: &[Magic]) -> Table {
+    let mut table = Table::new(&[
+        "Magic",
+        "Rank",
+        "Type",
+        "Always def",
+        "Table addon",
+        "Race mult",
+    ]);
+    for mag in magics {
+        table.add_row(&[
+            mag.name.clone(),
+            format!("{:?}", mag.rank),
+            mag.typ.to_string(),
+            mag.always_def.to_string(),
+            mag.table_addon.to_string(),
+            mag.race_mult.to_string(),
+        ]);
+    }
+    table
+}
+
+/// The variables set in `context`, sorted by name.
+pub fn variable_table(context: &HashMapContext) -> Table {
+    let mut variables: Vec<(String, String)> = context
+        .iter_variables()
+        .map(|(name, value)| (name, value.to_string()))
+        .collect();
+    variables.sort();
+
+    let mut table = Table::new(&["Variable", "Value"]);
+    for (name, value) in variables {
+        table.add_row(&[name, value]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::eval_with_context_mut;
+    use rpgcalc::MagicRank;
+
+    #[test]
+    fn parsing_commands() {
+        assert_eq!(MetaCommand::parse(":list"), Some(Ok(MetaCommand::List)));
+        assert_eq!(MetaCommand::parse("  :q "), Some(Ok(MetaCommand::Quit)));
+        assert_eq!(MetaCommand::parse("fire(10, 10)"), None);
+        assert_eq!(
+            MetaCommand::parse(":save  prep session.calc "),
+            Some(Ok(MetaCommand::Save(Some("prep session.calc".to_string()))))
+        );
+        assert_eq!(
+            MetaCommand::parse(":load"),
+            Some(Ok(MetaCommand::Load(None)))
+        );
+        assert_eq!(
+            MetaCommand::parse(":list all"),
+            Some(Err("`:list` doesn't take an argument".to_string()))
+        );
+        assert_eq!(
+            MetaCommand::parse(":lst all"),
+            Some(Err("unknown command `:lst`, see `:help`".to_string()))
+        );
+    }
+
+    #[test]
+    fn listing_magics_and_variables() {
+        let mut fire = Magic::new("fire");
+        fire.rank = MagicRank::Epic;
+        fire.race_mult = 1.2;
+        assert_eq!(
+            magic_table(&[fire]).rows,
+            [["fire", "Epic", "ORDER", "false", "0", "1.2"]]
+        );
+
+        let mut context = HashMapContext::new();
+        eval_with_context_mut("b = 2; a = 1.5", &mut context).unwrap();
+        assert_eq!(variable_table(&context).rows, [["a", "1.5"], ["b", "2"]]);
+    }
+}
@@ -0,0 +1,689 @@
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType},
+};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, stdout, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::watch::Watcher;
+
+/// How many lines the history keeps, in memory and on disk.
+const MAX_HISTORY: usize = 1000;
+
+/// The line being edited and the cursor in it, counted in characters.
+#[derive(Debug, Default, PartialEq)]
+pub struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    pub fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        Self {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Deletes the word before the cursor, and the spaces after it.
+    pub fn delete_word(&mut self) {
+        let mut start = self.cursor;
+        while start > 0 && self.chars[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.chars[start - 1] != ' ' {
+            start -= 1;
+        }
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn delete_to_start(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Deletes everything after the cursor.
+    pub fn delete_to_end(&mut self) {
+        self.chars.truncate(self.cursor);
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+}
+
+/// Lines entered in earlier prompts, oldest first, optionally kept in a
+/// file between sessions.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// The history file in the user's data directory, e.g.
+    /// `~/.local/share/rpgcalc/history` on Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        let data_dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else {
+            env::var_os("XDG_DATA_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+        };
+        data_dir.map(|dir| dir.join("rpgcalc").join("history"))
+    }
+
+    /// Loads the history kept in `path`. A missing file is an empty
+    /// history.
+    pub fn load(path: PathBuf) -> Self {
+        let mut entries: Vec<String> = fs::read_to_string(&path)
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        if entries.len() > MAX_HISTORY {
+            entries.drain(..entries.len() - MAX_HISTORY);
+            // Best effort: a history that can't be trimmed still works.
+            let _ = fs::write(&path, entries.join("\n") + "\n");
+        }
+        Self {
+            entries,
+            path: Some(path),
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Adds a line, unless it is blank or the same as the last one, and
+    /// appends it to the history file.
+    pub fn push(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+
+        if let Some(path) = &self.path {
+            let written = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+                .and_then(|mut file| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                eprintln!(
+                    "WARNING: could not save history to {}: {}",
+                    path.display(),
+                    e
+                );
+                self.path = None;
+            }
+        }
+    }
+
+    /// The newest entry before `before` containing `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+/// What [`Editor::read_line`] read.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Line(String),
+    /// The input ended, e.g. with Ctrl-D on an empty line.
+    Eof,
+    /// The watched files changed while the line was being typed. The line
+    /// is kept for the next call.
+    FilesChanged,
+}
+
+/// What Tab does.
+#[derive(Debug, PartialEq)]
+pub enum Completion {
+    /// Insert text at the cursor.
+    Insert(String),
+    /// Show text under the line, e.g. the names that match or the usage of
+    /// a function.
+    Show(String),
+    Nothing,
+}
+
+/// The names Tab can complete.
+#[derive(Debug, Default)]
+pub struct Completions {
+    /// Function names, with their usage when it is known.
+    pub functions: Vec<(String, Option<String>)>,
+    pub variables: Vec<String>,
+}
+
+impl Completions {
+    /// Completes the word that ends at the cursor, given the text before
+    /// it. With no word to complete, shows the usage of the function whose
+    /// parentheses the cursor is in.
+    pub fn complete(&self, before_cursor: &str) -> Completion {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+        let start = before_cursor
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_word(c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &before_cursor[start..];
+
+        if word.starts_with(|c: char| !c.is_ascii_digit()) {
+            let functions = self.functions.iter().map(|(name, _)| name);
+            let mut names: Vec<&String> = functions
+                .chain(&self.variables)
+                .filter(|name| name.starts_with(word))
+                .collect();
+            names.sort();
+            names.dedup();
+
+            match names[..] {
+                [] => {}
+                [name] => {
+                    let mut rest = name[word.len()..].to_string();
+                    if !self.variables.contains(name) {
+                        rest.push('(');
+                    }
+                    return Completion::Insert(rest);
+                }
+                _ => {
+                    let common = names[1..].iter().fold(names[0].as_str(), |common, name| {
+                        let len = common
+                            .char_indices()
+                            .zip(name.chars())
+                            .find(|&((_, a), b)| a != b)
+                            .map_or(common.len().min(name.len()), |((i, _), _)| i);
+                        &common[..len]
+                    });
+                    if common.len() > word.len() {
+                        return Completion::Insert(common[word.len()..].to_string());
+                    }
+                    let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                    return Completion::Show(names.join("  "));
+                }
+            }
+        }
+
+        let mut depth = 0;
+        for (i, c) in before_cursor.char_indices().rev() {
+            match c {
+                ')' => depth += 1,
+                '(' if depth > 0 => depth -= 1,
+                '(' => {
+                    let before = before_cursor[..i].trim_end();
+                    let start = before
+                        .char_indices()
+                        .rev()
+                        .find(|&(_, c)| !is_word(c))
+                        .map_or(0, |(i, c)| i + c.len_utf8());
+                    let name = &before[start..];
+                    return self
+                        .functions
+                        .iter()
+                        .find(|(function, _)| function == name)
+                        .and_then(|(_, usage)| usage.clone())
+                        .map_or(Completion::Nothing, Completion::Show);
+                }
+                _ => {}
+            }
+        }
+        Completion::Nothing
+    }
+}
+
+/// A Ctrl-R search through the history.
+struct Search {
+    query: String,
+    /// The entry found, if any.
+    found: Option<usize>,
+}
+
+/// Reads lines from the terminal with cursor movement, history and reverse
+/// search.
+pub struct Editor {
+    history: History,
+    pub completions: Completions,
+    /// Files to watch while waiting for keys, see [`Input::FilesChanged`].
+    pub watcher: Option<Watcher>,
+    /// A line interrupted by [`Input::FilesChanged`].
+    pending: Option<LineBuffer>,
+    prompt: &'static str,
+    prompt_color: Option<Color>,
+    input_color: Option<Color>,
+}
+
+impl Editor {
+    pub fn new(
+        history: History,
+        prompt: &'static str,
+        prompt_color: Option<Color>,
+        input_color: Option<Color>,
+    ) -> Self {
+        Self {
+            history,
+            completions: Completions::default(),
+            watcher: None,
+            pending: None,
+            prompt,
+            prompt_color,
+            input_color,
+        }
+    }
+
+    /// Reads a line and adds it to the history.
+    pub fn read_line(&mut self) -> io::Result<Input> {
+        terminal::enable_raw_mode()?;
+        let input = self.edit();
+        terminal::disable_raw_mode()?;
+        execute!(stdout(), ResetColor, Print("\n"))?;
+
+        let input = input?;
+        if let Input::Line(line) = &input {
+            self.history.push(line);
+        }
+        Ok(input)
+    }
+
+    /// Waits for a key, checking the watched files every half second.
+    /// `None` means they changed.
+    fn next_event(&mut self) -> io::Result<Option<Event>> {
+        if let Some(watcher) = &mut self.watcher {
+            while !event::poll(Duration::from_millis(500))? {
+                if watcher.changed() {
+                    return Ok(None);
+                }
+            }
+        }
+        event::read().map(Some)
+    }
+
+    fn edit(&mut self) -> io::Result<Input> {
+        let mut line = self.pending.take().unwrap_or_default();
+        let mut search: Option<Search> = None;
+        // The history entry shown, and the line that was being typed
+        // before browsing.
+        let mut browsing: Option<usize> = None;
+        let mut draft = String::new();
+
+        loop {
+            match &search {
+                Some(search) => self.draw_search(search)?,
+                None => self.draw(&line)?,
+            }
+            let Some(event) = self.next_event()? else {
+                self.pending = Some(line);
+                return Ok(Input::FilesChanged);
+            };
+            let Event::Key(key) = event else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            if let Some(current) = &mut search {
+                match self.search_key(current, key) {
+                    SearchOutcome::Continue => {}
+                    SearchOutcome::Accept(run) => {
+                        if let Some(found) = current.found {
+                            line = LineBuffer::new(&self.history.entries()[found]);
+                        }
+                        search = None;
+                        if run {
+                            self.draw(&line)?;
+                            return Ok(Input::Line(line.text()));
+                        }
+                    }
+                    SearchOutcome::Cancel => search = None,
+                }
+                continue;
+            }
+
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Enter => return Ok(Input::Line(line.text())),
+                KeyCode::Char('d') if ctrl => {
+                    if line.is_empty() {
+                        return Ok(Input::Eof);
+                    }
+                    line.delete();
+                }
+                KeyCode::Char('c') if ctrl => {
+                    execute!(stdout(), Print("^C"))?;
+                    return Ok(Input::Line(String::new()));
+                }
+                KeyCode::Char('r') if ctrl => {
+                    search = Some(Search {
+                        query: String::new(),
+                        found: None,
+                    })
+                }
+                KeyCode::Char('a') if ctrl => line.home(),
+                KeyCode::Char('e') if ctrl => line.end(),
+                KeyCode::Char('u') if ctrl => line.delete_to_start(),
+                KeyCode::Char('k') if ctrl => line.delete_to_end(),
+                KeyCode::Char('w') if ctrl => line.delete_word(),
+                KeyCode::Tab => match self.completions.complete(&line.before_cursor()) {
+                    Completion::Insert(text) => text.chars().for_each(|c| line.insert(c)),
+                    Completion::Show(text) => execute!(
+                        stdout(),
+                        ResetColor,
+                        Print("\r\n"),
+                        Print(text),
+                        Print("\r\n")
+                    )?,
+                    Completion::Nothing => {}
+                },
+                KeyCode::Char(c) if !ctrl => line.insert(c),
+                KeyCode::Backspace => line.backspace(),
+                KeyCode::Delete => line.delete(),
+                KeyCode::Left => line.left(),
+                KeyCode::Right => line.right(),
+                KeyCode::Home => line.home(),
+                KeyCode::End => line.end(),
+                KeyCode::Up => {
+                    let entries = self.history.entries();
+                    let previous = browsing.unwrap_or(entries.len()).checked_sub(1);
+                    if let Some(previous) = previous {
+                        if browsing.is_none() {
+                            draft = line.text();
+                        }
+                        browsing = Some(previous);
+                        line = LineBuffer::new(&entries[previous]);
+                    }
+                }
+                KeyCode::Down => {
+                    if let Some(current) = browsing {
+                        let entries = self.history.entries();
+                        if current + 1 < entries.len() {
+                            browsing = Some(current + 1);
+                            line = LineBuffer::new(&entries[current + 1]);
+                        } else {
+                            browsing = None;
+                            line = LineBuffer::new(&draft);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn search_key(&self, search: &mut Search, key: KeyEvent) -> SearchOutcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let entries = self.history.entries().len();
+        match key.code {
+            KeyCode::Enter => return SearchOutcome::Accept(true),
+            KeyCode::Char('g') | KeyCode::Char('c') if ctrl => return SearchOutcome::Cancel,
+            KeyCode::Esc => return SearchOutcome::Cancel,
+            KeyCode::Char('r') if ctrl => {
+                let before = search.found.unwrap_or(entries);
+                if let Some(found) = self.history.search(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            KeyCode::Char(c) if !ctrl => {
+                search.query.push(c);
+                let before = search.found.map_or(entries, |found| found + 1);
+                search.found = self.history.search(&search.query, before);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.found = if search.query.is_empty() {
+                    None
+                } else {
+                    self.history.search(&search.query, entries)
+                };
+            }
+            KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End => {
+                return SearchOutcome::Accept(false)
+            }
+            _ => {}
+        }
+        SearchOutcome::Continue
+    }
+
+    fn draw(&self, line: &LineBuffer) -> io::Result<()> {
+        let mut stdout = stdout();
+        queue!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+        if let Some(color) = self.prompt_color {
+            queue!(stdout, SetForegroundColor(color))?;
+        }
+        queue!(stdout, Print(self.prompt), ResetColor)?;
+        if let Some(color) = self.input_color {
+            queue!(stdout, SetForegroundColor(color))?;
+        }
+        let column = self.prompt.chars().count() + line.cursor();
+        queue!(
+            stdout,
+            Print(line.text()),
+            MoveToColumn(u16::try_from(column).unwrap_or(u16::MAX))
+        )?;
+        stdout.flush()
+    }
+
+    fn draw_search(&self, search: &Search) -> io::Result<()> {
+        let found = search
+            .found
+            .map_or("", |found| self.history.entries()[found].as_str());
+        let failed = if search.found.is_none() && !search.query.is_empty() {
+            "failed "
+        } else {
+            ""
+        };
+        execute!(
+            stdout(),
+            MoveToColumn(0),
+            Clear(ClearType::CurrentLine),
+            ResetColor,
+            Print(format!(
+                "({}reverse-i-search)`{}': {}",
+                failed, search.query, found
+            ))
+        )
+    }
+}
+
+enum SearchOutcome {
+    Continue,
+    /// Put the entry found in the line, and run it when `true`.
+    Accept(bool),
+    Cancel,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editing_the_line() {
+        let mut line = LineBuffer::new("fire(10)");
+        line.left();
+        line.insert(',');
+        line.insert(' ');
+        line.insert('5');
+        assert_eq!(line.text(), "fire(10, 5)");
+        assert_eq!(line.cursor(), 10);
+
+        line.home();
+        line.delete();
+        line.right();
+        line.backspace();
+        assert_eq!(line.text(), "re(10, 5)");
+
+        line.end();
+        line.delete_word();
+        assert_eq!(line.text(), "re(10, ");
+        line.home();
+        line.right();
+        line.delete_to_end();
+        assert_eq!(line.text(), "r");
+    }
+
+    fn completions() -> Completions {
+        Completions {
+            functions: vec![
+                (
+                    "fire".to_string(),
+                    Some("fire(<accuracy>, <mana>)".to_string()),
+                ),
+                (
+                    "def_fire".to_string(),
+                    Some("def_fire(<accuracy>, <mana>)".to_string()),
+                ),
+                (
+                    "t_fire".to_string(),
+                    Some("t_fire(<start>, <end>)".to_string()),
+                ),
+                (
+                    "t_def_fire".to_string(),
+                    Some("t_def_fire(<start>, <end>)".to_string()),
+                ),
+                ("floor".to_string(), None),
+            ],
+            variables: vec!["fireball".to_string()],
+        }
+    }
+
+    #[test]
+    fn completing_names() {
+        let completions = completions();
+        assert_eq!(
+            completions.complete("t_d"),
+            Completion::Insert("ef_fire(".to_string())
+        );
+        assert_eq!(
+            completions.complete("1 + fireb"),
+            Completion::Insert("all".to_string())
+        );
+        assert_eq!(
+            completions.complete("t_"),
+            Completion::Show("t_def_fire  t_fire".to_string())
+        );
+        assert_eq!(
+            completions.complete("fi"),
+            Completion::Insert("re".to_string())
+        );
+        assert_eq!(completions.complete("12"), Completion::Nothing);
+    }
+
+    #[test]
+    fn showing_signatures() {
+        let completions = completions();
+        assert_eq!(
+            completions.complete("fire("),
+            Completion::Show("fire(<accuracy>, <mana>)".to_string())
+        );
+        assert_eq!(
+            completions.complete("max(3, t_fire(1, floor(2.5), "),
+            Completion::Show("t_fire(<start>, <end>)".to_string())
+        );
+        assert_eq!(completions.complete("floor("), Completion::Nothing);
+        assert_eq!(completions.complete("(1 + "), Completion::Nothing);
+    }
+
+    #[test]
+    fn history_skips_blanks_and_repeats() {
+        let mut history = History::default();
+        for line in ["fire(10, 10)", "", "fire(10, 10)", "t_fire(1, 5)"] {
+            history.push(line);
+        }
+        assert_eq!(history.entries(), ["fire(10, 10)", "t_fire(1, 5)"]);
+    }
+
+    #[test]
+    fn reverse_search_goes_back_in_time() {
+        let mut history = History::default();
+        for line in ["fire(10, 10)", "a = 3", "fire(12, 20)"] {
+            history.push(line);
+        }
+        assert_eq!(history.search("fire", 3), Some(2));
+        assert_eq!(history.search("fire", 2), Some(0));
+        assert_eq!(history.search("fire", 0), None);
+        assert_eq!(history.search("ice", 3), None);
+    }
+
+    #[test]
+    fn history_file_keeps_the_newest_lines() {
+        let path = env::temp_dir().join(format!("rpgcalc-history-{}", std::process::id()));
+        let old: Vec<String> = (0..MAX_HISTORY + 5).map(|i| i.to_string()).collect();
+        fs::write(&path, old.join("\n")).unwrap();
+
+        let mut history = History::load(path.clone());
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(history.entries()[0], "5");
+        history.push("fire(1, 1)");
+
+        let reloaded = History::load(path.clone());
+        assert_eq!(reloaded.entries().last().unwrap(), "fire(1, 1)");
+        fs::remove_file(path).unwrap();
+    }
+}
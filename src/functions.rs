@@ -0,0 +1,1443 @@
+//! The evalexpr functions generated for every magic.
+
+use crate::plot::Chart;
+use crate::table::{Highlight, SharedTableOutput, Table, TableFormat};
+use evalexpr::*;
+
+use crate::calc::{
+    calculate_damage_splits, calculate_defense_life, calculate_defense_splits,
+    calculate_effective_damage, calculate_mana_for_damage, calculate_mana_for_defense,
+    has_parity_penalty, MAX_MANA,
+};
+use crate::magic::{Magic, MagicRank, MagicType};
+use crate::rules::{Origin, Ruleset};
+use std::sync::Arc;
+
+/// The prefix of the functions of a mode: none for the magic's own mode,
+/// `def_` for defending with an attack magic and `at_` for attacking with
+/// an `always_def` one.
+fn mode_prefix(always_defensive: bool, should_be_defensive: bool) -> &'static str {
+    match (always_defensive, should_be_defensive) {
+        (false, true) => "def_",
+        (true, false) => "at_",
+        _ => "",
+    }
+}
+
+fn default_help_message(
+    magic_name: &str,
+    always_defensive: bool,
+    should_be_defensive: bool,
+) -> String {
+    let prefix = mode_prefix(always_defensive, should_be_defensive);
+    format!("Use {}{}(<accuracy>, <mana>)", prefix, magic_name)
+}
+
+fn table_help_message(
+    magic_name: &str,
+    always_defensive: bool,
+    should_be_defensive: bool,
+) -> String {
+    let prefix = mode_prefix(always_defensive, should_be_defensive);
+    format!(
+        "Use t_{}{}(<start>, <end>, <?step>, <?accuracy>)",
+        prefix, magic_name
+    )
+}
+
+fn grid_help_message(
+    magic_name: &str,
+    always_defensive: bool,
+    should_be_defensive: bool,
+) -> String {
+    let prefix = mode_prefix(always_defensive, should_be_defensive);
+    format!(
+        "Use grid_{}{}(<mana_start>, <mana_end>, <accuracy_start>, <accuracy_end>, <?mana_step>, <?accuracy_step>, <?highlight>)",
+        prefix, magic_name
+    )
+}
+
+fn need_help_message(
+    magic_name: &str,
+    always_defensive: bool,
+    should_be_defensive: bool,
+) -> String {
+    let prefix = mode_prefix(always_defensive, should_be_defensive);
+    format!("Use need_{}{}(<target>, <accuracy>)", prefix, magic_name)
+}
+
+fn best_help_message(
+    magic_name: &str,
+    always_defensive: bool,
+    should_be_defensive: bool,
+) -> String {
+    let prefix = mode_prefix(always_defensive, should_be_defensive);
+    format!(
+        "Use best_{}{}(<budget>, <?cost_per_accuracy>)",
+        prefix, magic_name
+    )
+}
+
+const COMPARE_HELP: &str =
+    "Use compare(<\"magic\" | (\"magic\", ...)>, <start>, <end>, <?step>, <?accuracy>, <?delta>)";
+
+const PLOT_HELP: &str =
+    "Use plot(<\"magic\" | (\"magic\", ...)>, <start>, <end>, <?step>, <?accuracy>)";
+
+fn plot_help_message(magic_name: &str) -> String {
+    format!(
+        "Use plot_{}(<start>, <end>, <?step>, <?accuracy>)",
+        magic_name
+    )
+}
+
+fn table_format_help_message() -> String {
+    format!(
+        "Use table_format(<?\"{}\">)",
+        TableFormat::ALL.map(|f| f.name()).join("\" | \"")
+    )
+}
+
+const SAVE_TABLE_HELP: &str = "Use save_table(<\"file\">, <?\"format\">)";
+
+fn extremum_help_message(max: bool) -> String {
+    format!(
+        "Use {}(<number | table>, ...)",
+        if max { "max" } else { "min" }
+    )
+}
+
+fn index_help_message(column: bool) -> String {
+    format!(
+        "Use {}(<table>, <index>)",
+        if column { "column" } else { "row" }
+    )
+}
+
+/// The origin a magic was resolved to. Init files can only use declared
+/// origins, but magics built by hand may name any.
+fn origin_of<'a>(origin: &'a Option<Origin>, typ: &MagicType) -> EvalexprResult<&'a Origin> {
+    origin
+        .as_ref()
+        .ok_or_else(|| EvalexprError::CustomMessage(format!("`{}` is not a declared origin", typ)))
+}
+
+fn generate_default_function(
+    should_be_defensive: bool,
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+) -> Function {
+    let origin = mag.origin(rules);
+    let rank = mag.rank;
+    let typ = mag.typ.clone();
+    let mult = mag.race_mult;
+    let name = mag.name.clone();
+    let always_defensive = mag.always_def;
+    Function::new(move |arguments: &Value| {
+        let args = if let Ok(result) = arguments.as_tuple() {
+            result
+        } else {
+            return Err(EvalexprError::CustomMessage(default_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            )));
+        };
+
+        if args.len() != 2 {
+            return Err(EvalexprError::CustomMessage(default_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            )));
+        };
+
+        if let (Value::Int(accuracy), Value::Int(mana)) = (&args[0], &args[1]) {
+            let origin = origin_of(&origin, &typ)?;
+            if !should_be_defensive {
+                Ok(Value::Int(calculate_effective_damage(
+                    origin, *accuracy, *mana, rank, mult,
+                )?))
+            } else {
+                Ok(Value::Int(calculate_defense_life(
+                    origin, *accuracy, *mana, rank, mult,
+                )?))
+            }
+        } else {
+            Err(EvalexprError::CustomMessage(default_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            )))
+        }
+    })
+}
+
+fn generate_default_table_function(
+    should_be_defensive: bool,
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let origin = mag.origin(rules);
+    let rank = mag.rank;
+    let typ = mag.typ.clone();
+    let mult = mag.race_mult;
+    let table_addon = mag.table_addon;
+    let name = mag.name.clone();
+    let always_defensive = mag.always_def;
+    Function::new(move |arguments| {
+        let origin = origin_of(&origin, &typ)?;
+        let args = if let Ok(result) = arguments.as_tuple() {
+            result
+        } else {
+            return Err(EvalexprError::CustomMessage(table_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            )));
+        };
+
+        if args.len() != 2 && args.len() != 3 && args.len() != 4 {
+            return Err(EvalexprError::CustomMessage(table_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            )));
+        };
+
+        if args.len() == 2 {
+            if let (Value::Int(start), Value::Int(end)) = (&args[0], &args[1]) {
+                let mut table = Table::new(&["Mana", "Damage", "Accuracy"]);
+                // The accuracy is the same in every row.
+                table.leave_out_of_value(2);
+
+                if !should_be_defensive {
+                    for i in *start..=*end {
+                        table.add_row(&[
+                            i,
+                            calculate_effective_damage(origin, 10 + table_addon, i, rank, mult)?,
+                            10 + table_addon,
+                        ]);
+                    }
+                } else {
+                    for i in *start..=*end {
+                        table.add_row(&[
+                            i,
+                            calculate_defense_life(origin, 10 + table_addon, i, rank, mult)?,
+                            10 + table_addon,
+                        ]);
+                    }
+                }
+
+                Ok(output.lock().unwrap().keep(table))
+            } else {
+                Err(EvalexprError::CustomMessage(table_help_message(
+                    &name,
+                    always_defensive,
+                    should_be_defensive,
+                )))
+            }
+        } else {
+            let accuracy: i64 = if args.len() == 4 {
+                if let Value::Int(acc) = &args[3] {
+                    *acc
+                } else {
+                    return Err(EvalexprError::CustomMessage(table_help_message(
+                        &name,
+                        always_defensive,
+                        should_be_defensive,
+                    )));
+                }
+            } else {
+                10 + table_addon
+            };
+
+            if let (Value::Int(start), Value::Int(end), Value::Int(step)) =
+                (&args[0], &args[1], &args[2])
+            {
+                if *step <= 0 {
+                    return Err(EvalexprError::CustomMessage(
+                        "the step must be positive".to_string(),
+                    ));
+                }
+                let mut table = Table::new(&["Mana", "Damage", "Accuracy"]);
+                table.leave_out_of_value(2);
+
+                if !should_be_defensive {
+                    for i in (*start..=*end).step_by(*step as usize) {
+                        table.add_row(&[
+                            i,
+                            calculate_effective_damage(origin, accuracy, i, rank, mult)?,
+                            accuracy,
+                        ]);
+                    }
+                } else {
+                    for i in (*start..=*end).step_by(*step as usize) {
+                        table.add_row(&[
+                            i,
+                            calculate_defense_life(origin, accuracy, i, rank, mult)?,
+                            accuracy,
+                        ]);
+                    }
+                }
+
+                Ok(output.lock().unwrap().keep(table))
+            } else {
+                Err(EvalexprError::CustomMessage(table_help_message(
+                    &name,
+                    always_defensive,
+                    should_be_defensive,
+                )))
+            }
+        }
+    })
+}
+
+/// Damage of a cast, or barrier life when `defensive`.
+fn cast_value(
+    defensive: bool,
+    origin: &Origin,
+    accuracy: i64,
+    mana: i64,
+    rank: MagicRank,
+    mult: f64,
+) -> EvalexprResult<i64> {
+    if defensive {
+        calculate_defense_life(origin, accuracy, mana, rank, mult)
+    } else {
+        calculate_effective_damage(origin, accuracy, mana, rank, mult)
+    }
+}
+
+/// A table of casts with mana in the rows and accuracy in the columns. When
+/// the last argument is `true`, cells whose accuracy suffers the odd
+/// accuracy penalty of CHAOS-like origins are highlighted.
+fn generate_grid_function(
+    should_be_defensive: bool,
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let origin = mag.origin(rules);
+    let rank = mag.rank;
+    let typ = mag.typ.clone();
+    let mult = mag.race_mult;
+    let name = mag.name.clone();
+    let always_defensive = mag.always_def;
+    Function::new(move |arguments: &Value| {
+        let help = || {
+            EvalexprError::CustomMessage(grid_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            ))
+        };
+        let mut args = arguments.as_tuple().map_err(|_| help())?;
+        let highlight = match args.last() {
+            Some(Value::Boolean(highlight)) => {
+                let highlight = *highlight;
+                args.pop();
+                highlight
+            }
+            _ => false,
+        };
+        let ints = args
+            .iter()
+            .map(|arg| arg.as_int())
+            .collect::<EvalexprResult<Vec<i64>>>()
+            .map_err(|_| help())?;
+        let (mana_start, mana_end, acc_start, acc_end, mana_step, acc_step) = match ints[..] {
+            [ms, me, as_, ae] => (ms, me, as_, ae, 1, 1),
+            [ms, me, as_, ae, mstep] => (ms, me, as_, ae, mstep, 1),
+            [ms, me, as_, ae, mstep, astep] => (ms, me, as_, ae, mstep, astep),
+            _ => return Err(help()),
+        };
+        if mana_step <= 0 || acc_step <= 0 {
+            return Err(EvalexprError::CustomMessage(
+                "steps must be positive".to_string(),
+            ));
+        }
+
+        let origin = origin_of(&origin, &typ)?;
+        let accuracies: Vec<i64> = (acc_start..=acc_end).step_by(acc_step as usize).collect();
+
+        let mut header = vec!["Mana \\ Accuracy".to_string()];
+        header.extend(accuracies.iter().map(|a| a.to_string()));
+        let mut table = Table::new(&header);
+
+        for mana in (mana_start..=mana_end).step_by(mana_step as usize) {
+            let mut row = vec![mana];
+            for &accuracy in &accuracies {
+                row.push(cast_value(
+                    should_be_defensive,
+                    origin,
+                    accuracy,
+                    mana,
+                    rank,
+                    mult,
+                )?);
+            }
+            table.add_row(&row);
+            for (column, &accuracy) in accuracies.iter().enumerate() {
+                if highlight && has_parity_penalty(origin, accuracy) {
+                    table.highlight_last(column + 1, Highlight::Penalty);
+                }
+            }
+        }
+
+        Ok(output.lock().unwrap().keep(table))
+    })
+}
+
+/// The least mana for the magic to deal `target` damage, or raise a barrier
+/// with `target` life when `should_be_defensive`, at a given accuracy.
+fn generate_need_function(
+    should_be_defensive: bool,
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+) -> Function {
+    let origin = mag.origin(rules);
+    let rank = mag.rank;
+    let typ = mag.typ.clone();
+    let mult = mag.race_mult;
+    let name = mag.name.clone();
+    let always_defensive = mag.always_def;
+    Function::new(move |arguments: &Value| {
+        let help = || {
+            EvalexprError::CustomMessage(need_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            ))
+        };
+        let args = arguments.as_tuple().map_err(|_| help())?;
+        let [Value::Int(target), Value::Int(accuracy)] = args.as_slice() else {
+            return Err(help());
+        };
+
+        let origin = origin_of(&origin, &typ)?;
+        let mana = if should_be_defensive {
+            calculate_mana_for_defense(origin, *target, *accuracy, rank, mult)
+        } else {
+            calculate_mana_for_damage(origin, *target, *accuracy, rank, mult)
+        }?;
+        mana.map(Value::Int).ok_or_else(|| {
+            EvalexprError::CustomMessage(format!(
+                "{} can't reach {} {} at accuracy {} with up to {} mana",
+                name,
+                target,
+                if should_be_defensive {
+                    "life"
+                } else {
+                    "damage"
+                },
+                accuracy,
+                MAX_MANA
+            ))
+        })
+    })
+}
+
+/// How many splits `best_` functions list.
+const BEST_CANDIDATES: usize = 5;
+
+/// Splits a budget between accuracy and mana to get the most damage, or
+/// barrier life when `should_be_defensive`. Returns the best split as
+/// `(accuracy, mana)`, printed after a table of the best few.
+fn generate_best_function(
+    should_be_defensive: bool,
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let origin = mag.origin(rules);
+    let rank = mag.rank;
+    let typ = mag.typ.clone();
+    let mult = mag.race_mult;
+    let name = mag.name.clone();
+    let always_defensive = mag.always_def;
+    Function::new(move |arguments: &Value| {
+        let help = || {
+            EvalexprError::CustomMessage(best_help_message(
+                &name,
+                always_defensive,
+                should_be_defensive,
+            ))
+        };
+        let args = match arguments {
+            Value::Tuple(args) => args.clone(),
+            arg => vec![arg.clone()],
+        };
+        let (budget, cost) = match args.as_slice() {
+            [Value::Int(budget)] => (*budget, 1.0),
+            [Value::Int(budget), cost] => (*budget, cost.as_number().map_err(|_| help())?),
+            _ => return Err(help()),
+        };
+        if cost <= 0.0 {
+            return Err(EvalexprError::CustomMessage(
+                "the cost per accuracy must be positive".to_string(),
+            ));
+        }
+
+        let origin = origin_of(&origin, &typ)?;
+        let splits = if should_be_defensive {
+            calculate_defense_splits(origin, budget, cost, BEST_CANDIDATES, rank, mult)
+        } else {
+            calculate_damage_splits(origin, budget, cost, BEST_CANDIDATES, rank, mult)
+        }?;
+        let Some(best) = splits.first() else {
+            return Err(EvalexprError::CustomMessage(format!(
+                "a budget of {} can't pay for any cast",
+                budget
+            )));
+        };
+
+        let mut table = Table::new(&[
+            "#",
+            "Accuracy",
+            "Mana",
+            if should_be_defensive {
+                "Life"
+            } else {
+                "Damage"
+            },
+        ]);
+        for (place, split) in splits.iter().enumerate() {
+            table.add_row(&[place as i64 + 1, split.accuracy, split.mana, split.value]);
+        }
+        let best = Value::Tuple(vec![Value::Int(best.accuracy), Value::Int(best.mana)]);
+        Ok(output.lock().unwrap().keep_for(table, best))
+    })
+}
+
+/// The kinds of function generated for every magic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FunctionKind {
+    /// Damage or defense of a single cast.
+    Single { defensive: bool },
+    /// A table of casts over a range of mana.
+    Table { defensive: bool },
+    /// A table of casts over ranges of mana and accuracy.
+    Grid { defensive: bool },
+    /// The least mana that reaches a target.
+    Need { defensive: bool },
+    /// The best split of a budget between accuracy and mana.
+    Best { defensive: bool },
+    /// A chart of both modes over a range of mana.
+    Plot,
+}
+
+/// Builds the kind of function for a mode, `true` being defensive.
+type ModeKind = fn(bool) -> FunctionKind;
+
+/// The name and kind of every function generated for a magic. The plain
+/// name is defensive for `always_def` magics, which get `at_` functions for
+/// attacking instead of `def_` ones for defending.
+fn generated_functions(mag: &Magic) -> Vec<(String, FunctionKind)> {
+    let kinds: [(&str, ModeKind); 5] = [
+        ("", |defensive| FunctionKind::Single { defensive }),
+        ("t_", |defensive| FunctionKind::Table { defensive }),
+        ("grid_", |defensive| FunctionKind::Grid { defensive }),
+        ("need_", |defensive| FunctionKind::Need { defensive }),
+        ("best_", |defensive| FunctionKind::Best { defensive }),
+    ];
+    let mut functions: Vec<(String, FunctionKind)> = kinds
+        .iter()
+        .flat_map(|&(kind_prefix, kind)| {
+            // The magic's own mode first, then the other one.
+            [mag.always_def, !mag.always_def].map(|defensive| {
+                (
+                    format!(
+                        "{}{}{}",
+                        kind_prefix,
+                        mode_prefix(mag.always_def, defensive),
+                        mag.name
+                    ),
+                    kind(defensive),
+                )
+            })
+        })
+        .collect();
+    functions.push(("plot_".to_string() + &mag.name, FunctionKind::Plot));
+    functions
+}
+
+/// The names of every function generated for a magic.
+pub fn function_names(mag: &Magic) -> Vec<String> {
+    generated_functions(mag)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Every function generated for a magic, paired with the name it is
+/// registered under.
+pub fn magic_functions(
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Vec<(String, Function)> {
+    generated_functions(mag)
+        .into_iter()
+        .map(|(name, kind)| {
+            let function = match kind {
+                FunctionKind::Single { defensive } => {
+                    generate_default_function(defensive, mag, rules)
+                }
+                FunctionKind::Table { defensive } => {
+                    generate_default_table_function(defensive, mag, rules, output)
+                }
+                FunctionKind::Grid { defensive } => {
+                    generate_grid_function(defensive, mag, rules, output)
+                }
+                FunctionKind::Need { defensive } => generate_need_function(defensive, mag, rules),
+                FunctionKind::Best { defensive } => {
+                    generate_best_function(defensive, mag, rules, output)
+                }
+                FunctionKind::Plot => generate_magic_plot_function(mag, rules, output),
+            };
+            (name, function)
+        })
+        .collect()
+}
+
+/// One column of a `compare` table, or one curve of a plot: a magic in one
+/// of its modes.
+#[derive(Clone)]
+struct Column {
+    function: String,
+    origin: Option<Origin>,
+    typ: MagicType,
+    rank: MagicRank,
+    race_mult: f64,
+    table_addon: i64,
+    defensive: bool,
+}
+
+impl Column {
+    /// A column for each single-cast function of `mag`, plain name first.
+    fn of_magic(mag: &Magic, rules: &Ruleset) -> Vec<Column> {
+        generated_functions(mag)
+            .into_iter()
+            .filter_map(|(function, kind)| match kind {
+                FunctionKind::Single { defensive } => Some(Column {
+                    function,
+                    origin: mag.origin(rules),
+                    typ: mag.typ.clone(),
+                    rank: mag.rank,
+                    race_mult: mag.race_mult,
+                    table_addon: mag.table_addon,
+                    defensive,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn value(&self, accuracy: i64, mana: i64) -> EvalexprResult<i64> {
+        let origin = origin_of(&self.origin, &self.typ)?;
+        cast_value(
+            self.defensive,
+            origin,
+            accuracy,
+            mana,
+            self.rank,
+            self.race_mult,
+        )
+    }
+}
+
+/// The columns named by the first argument of `compare` or `plot`, a string
+/// or a tuple of strings.
+fn select_columns(
+    columns: &[Column],
+    names: Value,
+    help: impl Fn() -> EvalexprError,
+) -> EvalexprResult<Vec<&Column>> {
+    let names = match names {
+        Value::String(name) => vec![name],
+        Value::Tuple(names) => names
+            .into_iter()
+            .map(|name| name.as_string())
+            .collect::<EvalexprResult<Vec<String>>>()
+            .map_err(|_| help())?,
+        _ => return Err(help()),
+    };
+    names
+        .iter()
+        .map(|name| {
+            columns.iter().find(|c| c.function == *name).ok_or_else(|| {
+                EvalexprError::CustomMessage(format!(
+                    "`{}` is not a magic, use the name of a damage or defense function such as `fire` or `def_fire`",
+                    name
+                ))
+            })
+        })
+        .collect()
+}
+
+/// `(start, end, step, accuracy)` from `<start>, <end>, <?step>, <?accuracy>`.
+fn range_args(
+    args: &[Value],
+    help: impl Fn() -> EvalexprError,
+) -> EvalexprResult<(i64, i64, i64, Option<i64>)> {
+    let ints = args
+        .iter()
+        .map(|arg| arg.as_int())
+        .collect::<EvalexprResult<Vec<i64>>>()
+        .map_err(|_| help())?;
+    let range = match ints[..] {
+        [start, end] => (start, end, 1, None),
+        [start, end, step] => (start, end, step, None),
+        [start, end, step, accuracy] => (start, end, step, Some(accuracy)),
+        _ => return Err(help()),
+    };
+    if range.2 <= 0 {
+        return Err(EvalexprError::CustomMessage(
+            "the step must be positive".to_string(),
+        ));
+    }
+    Ok(range)
+}
+
+/// A table with one column per named single-cast function, e.g.
+/// `compare(("fire", "def_ice"), 1, 20)`, each with its magic's rank,
+/// origin and race multiplier. The best value of each row is highlighted.
+/// When the last argument is `true`, every column after the first shows
+/// its difference to the first.
+fn generate_compare_function(
+    magics: &[Magic],
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let columns: Vec<Column> = magics
+        .iter()
+        .flat_map(|mag| Column::of_magic(mag, rules))
+        .collect();
+
+    Function::new(move |arguments: &Value| {
+        let help = || EvalexprError::CustomMessage(COMPARE_HELP.to_string());
+        let mut args = arguments.as_tuple().map_err(|_| help())?;
+        let delta = match args.last() {
+            Some(Value::Boolean(delta)) => {
+                let delta = *delta;
+                args.pop();
+                delta
+            }
+            _ => false,
+        };
+        if args.is_empty() {
+            return Err(help());
+        }
+
+        let selected = select_columns(&columns, args.remove(0), help)?;
+        let (start, end, step, accuracy) = range_args(&args, help)?;
+        let accuracies: Vec<i64> = selected
+            .iter()
+            .map(|c| accuracy.unwrap_or(10 + c.table_addon))
+            .collect();
+
+        let mut header = vec!["Mana".to_string()];
+        header.extend(
+            selected
+                .iter()
+                .zip(&accuracies)
+                .map(|(column, accuracy)| format!("{} (accuracy {})", column.function, accuracy)),
+        );
+        let mut table = Table::new(&header);
+
+        for mana in (start..=end).step_by(step as usize) {
+            let values = selected
+                .iter()
+                .zip(&accuracies)
+                .map(|(c, &accuracy)| c.value(accuracy, mana))
+                .collect::<EvalexprResult<Vec<i64>>>()?;
+            let best = values.iter().copied().max().unwrap_or_default();
+
+            let mut row = vec![mana.to_string()];
+            for (i, &value) in values.iter().enumerate() {
+                row.push(if delta && i > 0 {
+                    format!("{:+}", value - values[0])
+                } else {
+                    value.to_string()
+                });
+            }
+            table.add_row(&row);
+            for (i, &value) in values.iter().enumerate() {
+                if value == best && values.len() > 1 {
+                    table.highlight_last(i + 1, Highlight::Best);
+                }
+            }
+        }
+
+        Ok(output.lock().unwrap().keep(table))
+    })
+}
+
+/// The tallest a plot gets, in rows.
+const PLOT_HEIGHT: usize = 20;
+
+/// Draws one curve per column over `<start>, <end>, <?step>, <?accuracy>`,
+/// as wide as the terminal, and gives the chart as a string.
+fn plot_columns(
+    selected: &[&Column],
+    args: &[Value],
+    output: &SharedTableOutput,
+    help: impl Fn() -> EvalexprError,
+) -> EvalexprResult<Value> {
+    let (start, end, step, accuracy) = range_args(args, help)?;
+    let mut chart = Chart::new(
+        "mana",
+        if selected.iter().all(|c| c.defensive) {
+            "life"
+        } else if selected.iter().any(|c| c.defensive) {
+            "damage / life"
+        } else {
+            "damage"
+        },
+    );
+    for column in selected {
+        let accuracy = accuracy.unwrap_or(10 + column.table_addon);
+        let points = (start..=end)
+            .step_by(step as usize)
+            .map(|mana| Ok((mana, column.value(accuracy, mana)?)))
+            .collect::<EvalexprResult<Vec<(i64, i64)>>>()?;
+        chart.add_series(
+            &format!("{} (accuracy {})", column.function, accuracy),
+            points,
+        );
+    }
+
+    let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+    let height = (height as usize).saturating_sub(8).min(PLOT_HEIGHT);
+    Ok(output
+        .lock()
+        .unwrap()
+        .keep_chart(chart, width as usize, height))
+}
+
+/// Plots both modes of a magic, e.g. `fire` and `def_fire`.
+fn generate_magic_plot_function(
+    mag: &Magic,
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let columns = Column::of_magic(mag, rules);
+    let name = mag.name.clone();
+    Function::new(move |arguments: &Value| {
+        let help = || EvalexprError::CustomMessage(plot_help_message(&name));
+        let args = arguments.as_tuple().map_err(|_| help())?;
+        let selected: Vec<&Column> = columns.iter().collect();
+        plot_columns(&selected, &args, &output, help)
+    })
+}
+
+/// Plots the named single-cast functions together, e.g.
+/// `plot(("fire", "ice"), 1, 20)`.
+fn generate_plot_function(
+    magics: &[Magic],
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> Function {
+    let output = Arc::clone(output);
+    let columns: Vec<Column> = magics
+        .iter()
+        .flat_map(|mag| Column::of_magic(mag, rules))
+        .collect();
+    Function::new(move |arguments: &Value| {
+        let help = || EvalexprError::CustomMessage(PLOT_HELP.to_string());
+        let mut args = arguments.as_tuple().map_err(|_| help())?;
+        if args.is_empty() {
+            return Err(help());
+        }
+        let selected = select_columns(&columns, args.remove(0), help)?;
+        plot_columns(&selected, &args, &output, help)
+    })
+}
+
+fn format_names() -> String {
+    TableFormat::ALL.map(|f| f.name()).join(", ")
+}
+
+/// `table_format()` gives the format tables are printed in and
+/// `table_format("csv")` changes it.
+fn generate_table_format_function(output: &SharedTableOutput) -> Function {
+    let output = Arc::clone(output);
+    Function::new(move |arguments: &Value| match arguments {
+        Value::Empty => Ok(Value::String(
+            output.lock().unwrap().format.name().to_string(),
+        )),
+        Value::String(name) => {
+            let format = TableFormat::from_name(name).ok_or_else(|| {
+                EvalexprError::CustomMessage(format!(
+                    "unknown table format `{}`, use one of {}",
+                    name,
+                    format_names()
+                ))
+            })?;
+            output.lock().unwrap().format = format;
+            Ok(Value::Empty)
+        }
+        _ => Err(EvalexprError::CustomMessage(table_format_help_message())),
+    })
+}
+
+/// `save_table("fire.csv")` writes the last table made, in the format
+/// given by the extension or by a second argument.
+fn generate_save_table_function(output: &SharedTableOutput) -> Function {
+    let output = Arc::clone(output);
+    Function::new(move |arguments: &Value| {
+        let help = || EvalexprError::CustomMessage(SAVE_TABLE_HELP.to_string());
+        let (path, format) = match arguments {
+            Value::String(path) => (path.clone(), None),
+            Value::Tuple(args) => match args.as_slice() {
+                [Value::String(path), Value::String(format)] => {
+                    (path.clone(), Some(format.clone()))
+                }
+                _ => return Err(help()),
+            },
+            _ => return Err(help()),
+        };
+
+        let format = match format {
+            Some(name) => TableFormat::from_name(&name),
+            None => TableFormat::from_path(&path),
+        }
+        .ok_or_else(|| {
+            EvalexprError::CustomMessage(format!(
+                "can't tell which format to save `{}` in, give one of {} as the second argument",
+                path,
+                format_names()
+            ))
+        })?;
+
+        output
+            .lock()
+            .unwrap()
+            .save(&path, format)
+            .map_err(EvalexprError::CustomMessage)?;
+        Ok(Value::Empty)
+    })
+}
+
+/// `row(table, index)` gives a row of a table returned by a table function
+/// and `column(table, index)` a column, both counting from 0.
+fn generate_index_function(column: bool) -> Function {
+    Function::new(move |arguments: &Value| {
+        let help = || EvalexprError::CustomMessage(index_help_message(column));
+        let args = arguments.as_tuple().map_err(|_| help())?;
+        let [Value::Tuple(rows), Value::Int(index)] = args.as_slice() else {
+            return Err(help());
+        };
+        let out_of_range = || {
+            EvalexprError::CustomMessage(format!(
+                "the table has no {} {}",
+                if column { "column" } else { "row" },
+                index
+            ))
+        };
+        let index = usize::try_from(*index).map_err(|_| out_of_range())?;
+
+        if !column {
+            return rows.get(index).cloned().ok_or_else(out_of_range);
+        }
+        rows.iter()
+            .map(|row| match row {
+                Value::Tuple(cells) => cells.get(index).cloned().ok_or_else(out_of_range),
+                _ => Err(help()),
+            })
+            .collect::<EvalexprResult<Vec<Value>>>()
+            .map(Value::Tuple)
+    })
+}
+
+/// Whether `value` is the rows of a table, a tuple of tuples.
+fn is_table(value: &Value) -> bool {
+    matches!(value, Value::Tuple(rows) if !rows.is_empty() && rows.iter().all(Value::is_tuple))
+}
+
+/// The numbers `max` and `min` compare in `value`: every cell of each row
+/// but the first, which is the mana the row is for, if it is a table, and
+/// `value` itself otherwise.
+fn extremum_candidates(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Tuple(rows) if is_table(value) => rows
+            .iter()
+            .flat_map(|row| row.as_tuple().unwrap_or_default().into_iter().skip(1))
+            .collect(),
+        value => vec![value.clone()],
+    }
+}
+
+/// evalexpr's `max` and `min`, which also take the tables returned by
+/// table functions, e.g. `max(t_fire(1, 20))` is the most damage in the
+/// table.
+fn generate_extremum_function(max: bool) -> Function {
+    Function::new(move |arguments: &Value| {
+        let candidates: Vec<Value> = match arguments {
+            Value::Tuple(args) if !is_table(arguments) => {
+                args.iter().flat_map(extremum_candidates).collect()
+            }
+            argument => extremum_candidates(argument),
+        };
+        if candidates.is_empty() {
+            return Err(EvalexprError::CustomMessage(extremum_help_message(max)));
+        }
+
+        if candidates.iter().all(Value::is_int) {
+            let ints = candidates.iter().filter_map(|c| c.as_int().ok());
+            let best = if max { ints.max() } else { ints.min() };
+            return Ok(Value::Int(best.unwrap_or_default()));
+        }
+        let floats = candidates
+            .iter()
+            .map(Value::as_number)
+            .collect::<EvalexprResult<Vec<f64>>>()?;
+        let best = floats
+            .into_iter()
+            .reduce(|a, b| if max { a.max(b) } else { a.min(b) });
+        Ok(Value::Float(best.unwrap_or_default()))
+    })
+}
+
+/// Functions that don't belong to a single magic.
+pub const CALCULATOR_FUNCTIONS: &[&str] = &[
+    "compare",
+    "plot",
+    "table_format",
+    "save_table",
+    "row",
+    "column",
+    "max",
+    "min",
+];
+
+/// The name and usage of every function [`register_magics`] registers for
+/// `magics`, e.g. `("fire", "fire(<accuracy>, <mana>)")`.
+pub fn signatures(magics: &[Magic]) -> Vec<(String, String)> {
+    let mut signatures: Vec<(String, String)> = magics
+        .iter()
+        .flat_map(|mag| {
+            generated_functions(mag).into_iter().map(|(name, kind)| {
+                let help = match kind {
+                    FunctionKind::Single { defensive } => {
+                        default_help_message(&mag.name, mag.always_def, defensive)
+                    }
+                    FunctionKind::Table { defensive } => {
+                        table_help_message(&mag.name, mag.always_def, defensive)
+                    }
+                    FunctionKind::Grid { defensive } => {
+                        grid_help_message(&mag.name, mag.always_def, defensive)
+                    }
+                    FunctionKind::Need { defensive } => {
+                        need_help_message(&mag.name, mag.always_def, defensive)
+                    }
+                    FunctionKind::Best { defensive } => {
+                        best_help_message(&mag.name, mag.always_def, defensive)
+                    }
+                    FunctionKind::Plot => plot_help_message(&mag.name),
+                };
+                (name, help)
+            })
+        })
+        .collect();
+    signatures.extend([
+        ("compare".to_string(), COMPARE_HELP.to_string()),
+        ("plot".to_string(), PLOT_HELP.to_string()),
+        ("table_format".to_string(), table_format_help_message()),
+        ("save_table".to_string(), SAVE_TABLE_HELP.to_string()),
+        ("row".to_string(), index_help_message(false)),
+        ("column".to_string(), index_help_message(true)),
+        ("max".to_string(), extremum_help_message(true)),
+        ("min".to_string(), extremum_help_message(false)),
+    ]);
+    for (_, help) in &mut signatures {
+        if let Some(usage) = help.strip_prefix("Use ") {
+            *help = usage.to_string();
+        }
+    }
+    signatures
+}
+
+/// Registers the functions of every magic into `context`, along with the
+/// [`CALCULATOR_FUNCTIONS`] that work across magics. Table functions return
+/// their rows and keep the table in `output`, and other functions that make
+/// a table or a chart keep it there too, see
+/// [`TableOutput::take_figure`](crate::table::TableOutput::take_figure). Run
+/// [`validate_magics`](crate::validate::validate_magics) first, or later
+/// magics may silently replace the functions of earlier ones.
+pub fn register_magics(
+    context: &mut HashMapContext,
+    magics: &[Magic],
+    rules: &Arc<Ruleset>,
+    output: &SharedTableOutput,
+) -> EvalexprResult<()> {
+    for mag in magics {
+        for (name, function) in magic_functions(mag, rules, output) {
+            context.set_function(name, function)?;
+        }
+    }
+    context.set_function(
+        "compare".to_string(),
+        generate_compare_function(magics, rules, output),
+    )?;
+    context.set_function(
+        "plot".to_string(),
+        generate_plot_function(magics, rules, output),
+    )?;
+    context.set_function(
+        "table_format".to_string(),
+        generate_table_format_function(output),
+    )?;
+    context.set_function(
+        "save_table".to_string(),
+        generate_save_table_function(output),
+    )?;
+    context.set_function("row".to_string(), generate_index_function(false))?;
+    context.set_function("column".to_string(), generate_index_function(true))?;
+    context.set_function("max".to_string(), generate_extremum_function(true))?;
+    context.set_function("min".to_string(), generate_extremum_function(false))?;
+    Ok(())
+}
+
+/// Replaces the magics in `context` with the ones `load` returns, keeping
+/// the user's variables. Functions of magics that are gone are removed, and
+/// their names are returned. If `load` fails its error is returned and
+/// neither `context` nor `magics` change.
+pub fn reload_magics<E>(
+    context: &mut HashMapContext,
+    magics: &mut Vec<Magic>,
+    output: &SharedTableOutput,
+    load: impl FnOnce() -> Result<(Vec<Magic>, Ruleset), E>,
+) -> Result<Vec<String>, E> {
+    let (new_magics, rules) = load()?;
+
+    context.clear_functions();
+    register_magics(context, &new_magics, &Arc::new(rules), output)
+        .expect("Function should not have any problems loading!");
+
+    let removed = magics
+        .iter()
+        .filter(|old| new_magics.iter().all(|new| new.name != old.name))
+        .map(|old| old.name.clone())
+        .collect();
+    *magics = new_magics;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_file::{parse_file, ParseError};
+    use crate::table::TableOutput;
+
+    fn ice() -> Magic {
+        let mut ice = Magic::new("ice");
+        ice.typ = MagicType::CHAOS;
+        ice
+    }
+
+    fn context(magics: &[Magic]) -> (HashMapContext, SharedTableOutput) {
+        let output = TableOutput::shared();
+        let mut context = HashMapContext::new();
+        register_magics(&mut context, magics, &Arc::new(Ruleset::default()), &output).unwrap();
+        (context, output)
+    }
+
+    fn ints(context: &mut HashMapContext, expr: &str) -> Vec<Vec<i64>> {
+        eval_tuple_with_context_mut(expr, context)
+            .unwrap()
+            .iter()
+            .map(|row| {
+                row.as_tuple()
+                    .unwrap()
+                    .iter()
+                    .map(|cell| cell.as_int().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn always_def_magics_return_single_values() {
+        let mut context = HashMapContext::new();
+        let magics = parse_file(
+            "register shield { always_def true }",
+            "test.rpg",
+            &mut Ruleset::default(),
+        )
+        .unwrap();
+        register_magics(
+            &mut context,
+            &magics,
+            &Arc::new(Ruleset::default()),
+            &SharedTableOutput::default(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            eval_with_context("shield(10, 3)", &context),
+            Ok(Value::Int(_))
+        ));
+        assert!(matches!(
+            eval_with_context("at_shield(10, 3)", &context),
+            Ok(Value::Int(_))
+        ));
+        assert_eq!(
+            eval_with_context("shield(10)", &context),
+            Err(EvalexprError::CustomMessage(
+                "Use shield(<accuracy>, <mana>)".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_with_context("at_shield(10)", &context),
+            Err(EvalexprError::CustomMessage(
+                "Use at_shield(<accuracy>, <mana>)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn grids_have_mana_rows_and_accuracy_columns() {
+        let (mut context, output) = context(&[ice()]);
+
+        let rows = ints(&mut context, "grid_ice(10, 20, 10, 13, 5, 1, true)");
+        assert_eq!(rows.len(), 3);
+        for (row, mana) in rows.iter().zip([10, 15, 20]) {
+            assert_eq!(row[0], mana);
+            for (cell, accuracy) in row[1..].iter().zip(10..=13) {
+                let single = format!("ice({}, {})", accuracy, mana);
+                assert_eq!(eval_with_context(&single, &context), Ok(Value::Int(*cell)));
+            }
+        }
+
+        let output = output.lock().unwrap();
+        let table = output.last.as_ref().unwrap();
+        assert_eq!(table.header, ["Mana \\ Accuracy", "10", "11", "12", "13"]);
+        for row in 0..3 {
+            let highlighted: Vec<usize> = (0..5)
+                .filter(|&column| table.highlight(row, column) == Some(Highlight::Penalty))
+                .collect();
+            // The odd accuracies 11 and 13.
+            assert_eq!(highlighted, [2, 4]);
+        }
+    }
+
+    #[test]
+    fn grid_arguments() {
+        let (mut context, output) = context(&[ice()]);
+
+        assert_eq!(
+            ints(&mut context, "grid_def_ice(1, 2, 10, 11)"),
+            [[1, 2, 2], [2, 7, 7]]
+        );
+        let table = output.lock().unwrap().last.clone().unwrap();
+        assert!((0..2).all(|row| (0..3).all(|column| table.highlight(row, column).is_none())));
+
+        let rows = ints(&mut context, "grid_ice(1, 5, 10, 14, 4, 2)");
+        assert_eq!(rows.iter().map(|row| row[0]).collect::<Vec<_>>(), [1, 5]);
+        assert!(rows.iter().all(|row| row.len() == 4));
+        assert_eq!(
+            output.lock().unwrap().last.as_ref().unwrap().header[1..],
+            ["10", "12", "14"]
+        );
+
+        let help = Err(EvalexprError::CustomMessage(grid_help_message(
+            "ice", false, false,
+        )));
+        assert_eq!(eval_with_context("grid_ice(1, 2, 10)", &context), help);
+        assert_eq!(
+            eval_with_context("grid_ice(1, 2, 10, 11, 1.5)", &context),
+            help
+        );
+        assert_eq!(
+            eval_with_context("grid_ice(1, 2, 10, 11, 0)", &context),
+            Err(EvalexprError::CustomMessage(
+                "steps must be positive".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn names_of_both_modes() {
+        let mut fire = Magic::new("fire");
+        assert_eq!(
+            function_names(&fire),
+            [
+                "fire",
+                "def_fire",
+                "t_fire",
+                "t_def_fire",
+                "grid_fire",
+                "grid_def_fire",
+                "need_fire",
+                "need_def_fire",
+                "best_fire",
+                "best_def_fire",
+                "plot_fire",
+            ]
+        );
+
+        fire.always_def = true;
+        let names = function_names(&fire);
+        assert_eq!(names[..4], ["fire", "at_fire", "t_fire", "t_at_fire"]);
+        assert_eq!(
+            generated_functions(&fire)[1].1,
+            FunctionKind::Single { defensive: false }
+        );
+        assert_eq!(
+            grid_help_message("fire", true, false),
+            "Use grid_at_fire(<mana_start>, <mana_end>, <accuracy_start>, <accuracy_end>, <?mana_step>, <?accuracy_step>, <?highlight>)"
+        );
+        assert_eq!(
+            default_help_message("fire", false, true),
+            "Use def_fire(<accuracy>, <mana>)"
+        );
+        assert_eq!(
+            table_help_message("fire", true, false),
+            "Use t_at_fire(<start>, <end>, <?step>, <?accuracy>)"
+        );
+    }
+
+    fn compared() -> (HashMapContext, SharedTableOutput) {
+        let mut fire = Magic::new("fire");
+        fire.table_addon = 2;
+        context(&[fire, ice()])
+    }
+
+    fn single(context: &HashMapContext, expr: String) -> i64 {
+        eval_int_with_context(&expr, context).unwrap()
+    }
+
+    #[test]
+    fn compare_uses_each_magic_and_highlights_the_best() {
+        let (mut context, output) = compared();
+
+        let rows = ints(&mut context, "compare((\"fire\", \"def_ice\"), 1, 9, 4)");
+        assert_eq!(rows.len(), 3);
+        for (row, mana) in rows.iter().zip([1, 5, 9]) {
+            // Each magic at its own default accuracy: 10 plus table_addon.
+            assert_eq!(
+                row[1..],
+                [
+                    single(&context, format!("fire(12, {})", mana)),
+                    single(&context, format!("def_ice(10, {})", mana)),
+                ]
+            );
+        }
+
+        let output = output.lock().unwrap();
+        let table = output.last.as_ref().unwrap();
+        assert_eq!(
+            table.header,
+            ["Mana", "fire (accuracy 12)", "def_ice (accuracy 10)"]
+        );
+        for (r, row) in rows.iter().enumerate() {
+            let best = row[1..].iter().max().unwrap();
+            for (column, value) in row.iter().enumerate().skip(1) {
+                assert_eq!(
+                    table.highlight(r, column) == Some(Highlight::Best),
+                    value == best
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compare_deltas_and_errors() {
+        let (mut context, output) = compared();
+
+        eval_with_context_mut(
+            "compare((\"fire\", \"ice\"), 1, 2, 1, 10, true)",
+            &mut context,
+        )
+        .unwrap();
+        let table = output.lock().unwrap().last.clone().unwrap();
+        assert_eq!(
+            table.header[1..],
+            ["fire (accuracy 10)", "ice (accuracy 10)"]
+        );
+        for (row, mana) in table.rows.iter().zip([1, 2]) {
+            let fire = single(&context, format!("fire(10, {})", mana));
+            let ice = single(&context, format!("ice(10, {})", mana));
+            assert_eq!(row[1], fire.to_string());
+            assert_eq!(row[2], format!("{:+}", ice - fire));
+            assert!(row[2].starts_with(['+', '-']));
+        }
+
+        // A single column has nothing to be best against.
+        eval_with_context_mut("compare(\"fire\", 1, 2)", &mut context).unwrap();
+        let table = output.lock().unwrap().last.clone().unwrap();
+        assert!((0..2).all(|row| table.highlight(row, 1).is_none()));
+
+        assert_eq!(
+            eval_with_context("compare((\"fire\", \"frost\"), 1, 2)", &context),
+            Err(EvalexprError::CustomMessage(
+                "`frost` is not a magic, use the name of a damage or defense function such as `fire` or `def_fire`"
+                    .to_string()
+            ))
+        );
+        assert!(eval_with_context("compare(\"t_fire\", 1, 2)", &context).is_err());
+        assert_eq!(
+            eval_with_context("compare((\"fire\", 3), 1, 2)", &context),
+            Err(EvalexprError::CustomMessage(COMPARE_HELP.to_string()))
+        );
+    }
+
+    fn load(content: &str) -> impl FnOnce() -> Result<(Vec<Magic>, Ruleset), ParseError> + '_ {
+        move || {
+            let mut rules = Ruleset::default();
+            let magics = parse_file(content, "test.rpg", &mut rules)?;
+            Ok((magics, rules))
+        }
+    }
+
+    #[test]
+    fn reloading_replaces_the_functions_and_keeps_variables() {
+        let (mut context, output) = context(&[]);
+        let mut magics = Vec::new();
+        reload_magics(
+            &mut context,
+            &mut magics,
+            &output,
+            load("register fire {}\nregister ice { type CHAOS }"),
+        )
+        .unwrap();
+        eval_with_context_mut("a = fire(10, 10)", &mut context).unwrap();
+
+        let removed =
+            reload_magics(&mut context, &mut magics, &output, load("register fire {}")).unwrap();
+        assert_eq!(removed, ["ice"]);
+        assert_eq!(magics.len(), 1);
+        assert!(eval_with_context("fire(10, 10)", &context).is_ok());
+        for name in ["ice", "def_ice", "t_ice", "plot_ice"] {
+            assert_eq!(
+                eval_with_context(&format!("{}(10, 10)", name), &context),
+                Err(EvalexprError::FunctionIdentifierNotFound(name.to_string()))
+            );
+        }
+        assert!(context.get_value("a").is_some());
+    }
+
+    #[test]
+    fn reloading_a_broken_file_keeps_the_previous_functions() {
+        let (mut context, output) = context(&[]);
+        let mut magics = Vec::new();
+        reload_magics(&mut context, &mut magics, &output, load("register fire {}")).unwrap();
+
+        assert!(reload_magics(&mut context, &mut magics, &output, load("register ice {")).is_err());
+        assert_eq!(magics.len(), 1);
+        assert_eq!(magics[0].name, "fire");
+        assert!(eval_with_context("fire(10, 10)", &context).is_ok());
+        assert_eq!(
+            eval_with_context("ice(10, 10)", &context),
+            Err(EvalexprError::FunctionIdentifierNotFound("ice".to_string()))
+        );
+    }
+}
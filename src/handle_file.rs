@@ -1,10 +1,15 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
-use std::fmt::format;
+use std::fmt;
 use std::fs;
+
+use crate::magic::{Magic, MagicRank, MagicType};
+use crate::rules::{
+    AccuracyCurve, DamageFormula, DefenseFormula, Formula, FormulaError, Ruleset,
+    ACCURACY_VARIABLES, DAMAGE_VARIABLES, DEFENSE_VARIABLES,
+};
 use std::string::String;
 use std::vec::Vec;
 
+#[derive(Clone, Copy, PartialEq)]
 enum KeywordDef {
     Register,
     Rank,
@@ -12,14 +17,47 @@ enum KeywordDef {
     AlwaysDefense,
     TableAddon,
     RaceMult,
+    Rules,
+    Multipliers,
+    Origin,
+    Accuracy,
+    Damage,
+    Defense,
     Start,
     End,
 }
 
-#[derive(PartialEq)]
-enum CustomIdentifier {
-    ORDER,
-    CHAOS,
+impl KeywordDef {
+    /// Whether the keyword is a property of a `register` block.
+    fn is_property(&self) -> bool {
+        matches!(
+            self,
+            KeywordDef::Rank
+                | KeywordDef::Type
+                | KeywordDef::AlwaysDefense
+                | KeywordDef::TableAddon
+                | KeywordDef::RaceMult
+                | KeywordDef::Accuracy
+        )
+    }
+
+    /// The kind of value a property keyword must be followed by.
+    fn expected(&self) -> Expected {
+        match self {
+            KeywordDef::Register | KeywordDef::Origin => Expected::Name,
+            KeywordDef::Rank => Expected::Rank,
+            KeywordDef::TableAddon => Expected::Int,
+            KeywordDef::Type => Expected::MagicType,
+            KeywordDef::AlwaysDefense => Expected::Bool,
+            KeywordDef::RaceMult => Expected::Float,
+            KeywordDef::Accuracy => Expected::AccuracyCurve,
+            KeywordDef::Damage => Expected::Formula,
+            KeywordDef::Defense => Expected::DefenseFormula,
+            KeywordDef::Rules | KeywordDef::Multipliers | KeywordDef::Start | KeywordDef::End => {
+                Expected::BlockStart
+            }
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -27,7 +65,6 @@ enum ValueType {
     Bool(bool),
     Int(i64),
     Float(f64),
-    Custom(CustomIdentifier),
 }
 
 enum Token {
@@ -36,246 +73,964 @@ enum Token {
     Name(String),
 }
 
-#[derive(Clone, Copy)]
-pub enum MagicType {
-    ORDER,
-    CHAOS,
+/// Where a token starts inside an init file. Lines and columns start at 1,
+/// and `len` is the width of the token in characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+struct SpannedToken {
+    token: Token,
+    text: String,
+    span: Span,
 }
 
-#[derive(Clone, Copy)]
-pub enum MagicRank {
-    Common,
-    Uncommon,
-    Epic,
-    Legendary,
-    Mythic,
-    Divine,
+/// The kind of token the interpreter was looking for when it failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Expected {
+    Name,
+    Int,
+    Float,
+    Bool,
+    MagicType,
+    Rank,
+    AccuracyCurve,
+    DefenseFormula,
+    Formula,
+    BlockStart,
 }
 
-pub struct Magic {
-    pub name: String,
-    pub rank: MagicRank,
-    pub typ: MagicType,
-    pub always_def: bool,
-    pub table_addon: i64,
-    pub race_mult: f64,
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Name => write!(f, "a name"),
+            Expected::Int => write!(f, "an Int"),
+            Expected::Float => write!(f, "a Float"),
+            Expected::Bool => write!(f, "a Bool"),
+            Expected::MagicType => write!(f, "an origin"),
+            Expected::Rank => write!(f, "a rank"),
+            Expected::AccuracyCurve => write!(
+                f,
+                "an accuracy curve (linear, logistic, steps, parity, capped, = <formula>)"
+            ),
+            Expected::DefenseFormula => {
+                write!(f, "a defense formula (scaled, scaled_log, = <formula>)")
+            }
+            Expected::Formula => write!(f, "`= <formula>`"),
+            Expected::BlockStart => write!(f, "`{{`"),
+        }
+    }
+}
+
+/// The kind of token that was found instead of the expected one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Found {
+    Name,
+    Int,
+    Float,
+    Bool,
+    Keyword,
+    Brace,
+}
+
+impl Found {
+    fn of(token: &Token) -> Self {
+        match token {
+            Token::Keyword(KeywordDef::Start | KeywordDef::End) => Found::Brace,
+            Token::Keyword(_) => Found::Keyword,
+            Token::Value(ValueType::Int(_)) => Found::Int,
+            Token::Value(ValueType::Float(_)) => Found::Float,
+            Token::Value(ValueType::Bool(_)) => Found::Bool,
+            Token::Name(_) => Found::Name,
+        }
+    }
 }
 
-impl Magic {
-    fn new() -> Self {
+impl fmt::Display for Found {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Found::Name => write!(f, "the name"),
+            Found::Int => write!(f, "the Int"),
+            Found::Float => write!(f, "the Float"),
+            Found::Bool => write!(f, "the Bool"),
+            Found::Keyword => write!(f, "the keyword"),
+            Found::Brace => write!(f, "the brace"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    /// The file could not be read at all.
+    Io(String),
+    /// A token of the wrong kind was found.
+    Unexpected { expected: Expected, found: Found },
+    /// The file ended while a value was still expected.
+    UnexpectedEof(Expected),
+    /// A rank outside of 0-5 or an unknown rank name.
+    InvalidRank,
+    /// `register <name>`, `rules` or another block keyword is not followed
+    /// by `{`.
+    MissingBlockStart,
+    /// A block is still open at the end of the file or at the next `register`.
+    /// Points at the opening brace.
+    UnclosedBlock,
+    /// A `}` without a matching `{`.
+    UnmatchedBlockEnd,
+    /// A `{` that does not follow `register <name>`.
+    NestedBlock,
+    /// A property keyword outside of any `register` block.
+    PropertyOutsideBlock,
+    /// A keyword that is not allowed where it appears, e.g. `multipliers`
+    /// inside a `register` block.
+    MisplacedKeyword,
+    /// A word that is not a keyword where a keyword was expected.
+    UnknownIdentifier,
+    /// A value that does not follow a property keyword.
+    StrayValue,
+    /// The same property appears twice in one block. Holds where it was
+    /// first set.
+    DuplicateProperty { line: usize, column: usize },
+    /// A `/*` comment was never closed.
+    UnterminatedComment,
+    /// A `type` or `multipliers` row names an origin that no `rules` block
+    /// declared before it.
+    UndeclaredOrigin,
+    /// A `steps` accuracy curve without any step.
+    EmptySteps,
+    /// A formula that evalexpr rejected or that reads unknown variables.
+    /// Points at the whole formula.
+    InvalidFormula(Box<FormulaError>),
+}
+
+/// An error found while loading an init file, pointing at the offending token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// The text of the offending token. Empty at the end of the file.
+    pub token: String,
+    pub kind: ParseErrorKind,
+    len: usize,
+    source_line: String,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, token: &SpannedToken) -> Self {
+        Self::at(kind, token.span, &token.text)
+    }
+
+    fn at(kind: ParseErrorKind, span: Span, token: &str) -> Self {
+        Self {
+            file: String::new(),
+            line: span.line,
+            column: span.column,
+            token: token.to_string(),
+            kind,
+            len: span.len,
+            source_line: String::new(),
+        }
+    }
+
+    fn at_eof(expected: Expected, tokens: &[SpannedToken]) -> Self {
+        let (line, column) = match tokens.last() {
+            Some(t) => (t.span.line, t.span.column + t.span.len),
+            None => (1, 1),
+        };
         Self {
-            name: String::new(),
-            rank: MagicRank::Common,
-            typ: MagicType::ORDER,
-            always_def: false,
-            table_addon: 0,
-            race_mult: 1.0,
+            file: String::new(),
+            line,
+            column,
+            token: String::new(),
+            kind: ParseErrorKind::UnexpectedEof(expected),
+            len: 1,
+            source_line: String::new(),
         }
     }
+
+    fn io(filename: &str, e: std::io::Error) -> Self {
+        Self {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            kind: ParseErrorKind::Io(e.to_string()),
+            len: 0,
+            source_line: String::new(),
+        }
+    }
+
+    fn with_source(mut self, filename: &str, content: &str) -> Self {
+        self.file = filename.to_string();
+        if self.line > 0 {
+            self.source_line = content
+                .lines()
+                .nth(self.line - 1)
+                .unwrap_or_default()
+                .to_string();
+        }
+        self
+    }
+
+    /// Renders the offending line with the bad token underlined, or `None`
+    /// when the error has no position (e.g. the file could not be read).
+    pub fn snippet(&self) -> Option<String> {
+        if self.line == 0 {
+            return None;
+        }
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!(
+            "{gutter} |\n{number} | {}\n{gutter} | {indent}{}",
+            self.source_line,
+            "^".repeat(self.len.max(1))
+        ))
+    }
 }
 
-fn parse_string<'a>(inp: &'a str) -> Vec<&'a str> {
-    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
-    return RE.split(&inp).collect();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let ParseErrorKind::Io(e) = &self.kind {
+            return write!(f, "{}: could not read file: {}", self.file, e);
+        }
+        write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::Unexpected { expected, found } => {
+                write!(f, "expected {}, found {} `{}`", expected, found, self.token)
+            }
+            ParseErrorKind::UnexpectedEof(expected) => {
+                write!(f, "expected {}, found end of file", expected)
+            }
+            ParseErrorKind::InvalidRank => write!(
+                f,
+                "`{}` is not a rank, use 0-5 or Common, Uncommon, Epic, Legendary, Mythic, Divine",
+                self.token
+            ),
+            ParseErrorKind::MissingBlockStart => {
+                write!(f, "expected `{{`, found `{}`", self.token)
+            }
+            ParseErrorKind::UnclosedBlock => write!(f, "this block is never closed with `}}`"),
+            ParseErrorKind::UnmatchedBlockEnd => write!(f, "`}}` has no matching `{{`"),
+            ParseErrorKind::NestedBlock => {
+                write!(f, "`{{` is only allowed right after `register <name>`")
+            }
+            ParseErrorKind::PropertyOutsideBlock => {
+                write!(
+                    f,
+                    "property `{}` is outside of a `register` block",
+                    self.token
+                )
+            }
+            ParseErrorKind::MisplacedKeyword => {
+                write!(f, "`{}` is not allowed here", self.token)
+            }
+            ParseErrorKind::UnknownIdentifier => write!(f, "unknown identifier `{}`", self.token),
+            ParseErrorKind::StrayValue => {
+                write!(f, "value `{}` does not belong to any property", self.token)
+            }
+            ParseErrorKind::DuplicateProperty { line, column } => write!(
+                f,
+                "property `{}` is already set at {}:{}",
+                self.token, line, column
+            ),
+            ParseErrorKind::UnterminatedComment => {
+                write!(f, "block comment is never closed with `*/`")
+            }
+            ParseErrorKind::UndeclaredOrigin => write!(
+                f,
+                "`{0}` is not a declared origin, declare it first with `origin {0} {{ ... }}` in a `rules` block",
+                self.token
+            ),
+            ParseErrorKind::InvalidFormula(e) => write!(f, "invalid formula `{}`: {}", self.token, e),
+            ParseErrorKind::EmptySteps => {
+                write!(f, "`steps` needs at least one `<accuracy> <factor>` pair")
+            }
+            ParseErrorKind::Io(_) => unreachable!(),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Splits the file into words, remembering where each one starts.
+/// Braces are always words of their own, even when glued to a name, and so
+/// is a `=` that is not part of an operator such as `>=` or `==`, so
+/// `damage=mana` reads like `damage = mana`.
+/// `#` line comments and `/* */` block comments are dropped here.
+fn parse_string(inp: &str) -> Result<Vec<(&str, Span)>, ParseError> {
+    let mut words = Vec::new();
+    let mut comment_start: Option<Span> = None;
+
+    for (line_index, line) in inp.lines().enumerate() {
+        let line_no = line_index + 1;
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let mut start: Option<(usize, Span)> = None;
+        let mut column_index = 0;
+
+        while column_index < chars.len() {
+            let (byte, c) = chars[column_index];
+            let previous = column_index
+                .checked_sub(1)
+                .map(|previous| chars[previous].1);
+            let next = chars.get(column_index + 1).map(|&(_, c)| c);
+            let span = Span {
+                line: line_no,
+                column: column_index + 1,
+                len: 1,
+            };
+
+            if comment_start.is_some() {
+                if c == '*' && next == Some('/') {
+                    comment_start = None;
+                    column_index += 1;
+                }
+                column_index += 1;
+                continue;
+            }
+
+            let opens_comment = c == '#' || (c == '/' && next == Some('*'));
+            let assigns = c == '='
+                && next != Some('=')
+                && !previous.is_some_and(|p| "<>=!+-*/%^&|:".contains(p));
+            if c.is_whitespace() || c == '{' || c == '}' || assigns || opens_comment {
+                if let Some((from, span)) = start.take() {
+                    words.push((&line[from..byte], span));
+                }
+                if c == '#' {
+                    break;
+                }
+                if opens_comment {
+                    comment_start = Some(Span { len: 2, ..span });
+                    column_index += 1;
+                } else if !c.is_whitespace() {
+                    words.push((&line[byte..byte + 1], span));
+                }
+                column_index += 1;
+                continue;
+            }
+
+            match &mut start {
+                Some((_, span)) => span.len += 1,
+                None => start = Some((byte, span)),
+            }
+            column_index += 1;
+        }
+        if let Some((from, span)) = start.take() {
+            words.push((&line[from..], span));
+        }
+    }
+
+    if let Some(span) = comment_start {
+        return Err(ParseError::at(
+            ParseErrorKind::UnterminatedComment,
+            span,
+            "/*",
+        ));
+    }
+
+    Ok(words)
 }
 
-fn parse_tokens(tokens_to_be: &Vec<&str>) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
+fn parse_tokens(tokens_to_be: &[(&str, Span)]) -> Vec<SpannedToken> {
+    let mut tokens: Vec<SpannedToken> = Vec::new();
 
-    for &s in tokens_to_be.iter() {
-        match s {
-            "register" => tokens.push(Token::Keyword(KeywordDef::Register)),
-            "rank" => tokens.push(Token::Keyword(KeywordDef::Rank)),
-            "type" => tokens.push(Token::Keyword(KeywordDef::Type)),
-            "always_def" => tokens.push(Token::Keyword(KeywordDef::AlwaysDefense)),
-            "table_addon" => tokens.push(Token::Keyword(KeywordDef::TableAddon)),
-            "race_mult" => tokens.push(Token::Keyword(KeywordDef::RaceMult)),
-            "{" => tokens.push(Token::Keyword(KeywordDef::Start)),
-            "}" => tokens.push(Token::Keyword(KeywordDef::End)),
-            "ORDER" => tokens.push(Token::Value(ValueType::Custom(CustomIdentifier::ORDER))),
-            "CHAOS" => tokens.push(Token::Value(ValueType::Custom(CustomIdentifier::CHAOS))),
-            "false" => tokens.push(Token::Value(ValueType::Bool(false))),
-            "true" => tokens.push(Token::Value(ValueType::Bool(true))),
+    for &(s, span) in tokens_to_be.iter() {
+        let token = match s {
+            "register" => Token::Keyword(KeywordDef::Register),
+            "rank" => Token::Keyword(KeywordDef::Rank),
+            "type" => Token::Keyword(KeywordDef::Type),
+            "always_def" => Token::Keyword(KeywordDef::AlwaysDefense),
+            "table_addon" => Token::Keyword(KeywordDef::TableAddon),
+            "race_mult" => Token::Keyword(KeywordDef::RaceMult),
+            "rules" => Token::Keyword(KeywordDef::Rules),
+            "multipliers" => Token::Keyword(KeywordDef::Multipliers),
+            "origin" => Token::Keyword(KeywordDef::Origin),
+            "accuracy" => Token::Keyword(KeywordDef::Accuracy),
+            "damage" => Token::Keyword(KeywordDef::Damage),
+            "defense" => Token::Keyword(KeywordDef::Defense),
+            "{" => Token::Keyword(KeywordDef::Start),
+            "}" => Token::Keyword(KeywordDef::End),
+            "false" => Token::Value(ValueType::Bool(false)),
+            "true" => Token::Value(ValueType::Bool(true)),
             _ => match s.parse::<i64>() {
-                Ok(value) => tokens.push(Token::Value(ValueType::Int(value))),
+                Ok(value) => Token::Value(ValueType::Int(value)),
                 Err(_) => match s.parse::<f64>() {
-                    Ok(value) => tokens.push(Token::Value(ValueType::Float(value))),
-                    Err(_) => tokens.push(Token::Name(s.to_string())),
+                    Ok(value) => Token::Value(ValueType::Float(value)),
+                    Err(_) => Token::Name(s.to_string()),
                 },
             },
-        }
+        };
+        tokens.push(SpannedToken {
+            token,
+            text: s.to_string(),
+            span,
+        });
     }
 
     tokens
 }
 
+/// Returns the token at `i`, or an error pointing at the end of the file when
+/// there is none.
 #[inline]
-fn is_out_of_bounds(tokens: &Vec<Token>, index: usize) -> bool {
-    return tokens.len() < index;
+fn token_at(
+    tokens: &[SpannedToken],
+    i: usize,
+    expected: Expected,
+) -> Result<&SpannedToken, ParseError> {
+    tokens
+        .get(i)
+        .ok_or_else(|| ParseError::at_eof(expected, tokens))
 }
 
 #[inline]
-fn i_to_magic_rank(i: i64) -> MagicRank {
+fn i_to_magic_rank(i: i64) -> Option<MagicRank> {
     match i {
-        0 => MagicRank::Common,
-        1 => MagicRank::Uncommon,
-        2 => MagicRank::Epic,
-        3 => MagicRank::Legendary,
-        4 => MagicRank::Mythic,
-        5 => MagicRank::Divine,
-        _ => MagicRank::Common,
+        0 => Some(MagicRank::Common),
+        1 => Some(MagicRank::Uncommon),
+        2 => Some(MagicRank::Epic),
+        3 => Some(MagicRank::Legendary),
+        4 => Some(MagicRank::Mythic),
+        5 => Some(MagicRank::Divine),
+        _ => None,
     }
 }
 
-fn interpret_tokens_to_magic(tokens: &Vec<Token>) -> Result<Vec<Magic>, &'static str> {
+#[inline]
+fn name_to_magic_rank(name: &str) -> Option<MagicRank> {
+    match name {
+        "Common" => Some(MagicRank::Common),
+        "Uncommon" => Some(MagicRank::Uncommon),
+        "Epic" => Some(MagicRank::Epic),
+        "Legendary" => Some(MagicRank::Legendary),
+        "Mythic" => Some(MagicRank::Mythic),
+        "Divine" => Some(MagicRank::Divine),
+        _ => None,
+    }
+}
+
+/// A property value after it was coerced into the type its keyword declares.
+enum PropertyValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Rank(MagicRank),
+    MagicType(MagicType),
+}
+
+/// Checks that `token` fits the `expected` kind, widening numbers where that
+/// loses nothing (an Int is accepted wherever a Float is).
+fn coerce(token: &SpannedToken, expected: Expected) -> Result<PropertyValue, ParseError> {
+    let value = match (expected, &token.token) {
+        (Expected::Int, Token::Value(ValueType::Int(v))) => Some(PropertyValue::Int(*v)),
+        (Expected::Float, Token::Value(ValueType::Float(v))) => Some(PropertyValue::Float(*v)),
+        (Expected::Float, Token::Value(ValueType::Int(v))) => Some(PropertyValue::Float(*v as f64)),
+        (Expected::Bool, Token::Value(ValueType::Bool(b))) => Some(PropertyValue::Bool(*b)),
+        (Expected::MagicType, Token::Name(n)) => Some(PropertyValue::MagicType(MagicType::new(n))),
+        (Expected::Rank, Token::Value(ValueType::Int(v))) => {
+            let rank = i_to_magic_rank(*v)
+                .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidRank, token))?;
+            Some(PropertyValue::Rank(rank))
+        }
+        (Expected::Rank, Token::Name(n)) => {
+            let rank = name_to_magic_rank(n)
+                .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidRank, token))?;
+            Some(PropertyValue::Rank(rank))
+        }
+        _ => None,
+    };
+
+    value.ok_or_else(|| unexpected(expected, token))
+}
+
+/// The error for a token of the wrong kind.
+fn unexpected(expected: Expected, token: &SpannedToken) -> ParseError {
+    ParseError::new(
+        ParseErrorKind::Unexpected {
+            expected,
+            found: Found::of(&token.token),
+        },
+        token,
+    )
+}
+
+/// Reads the Float at `i`, accepting an Int.
+fn float_at(tokens: &[SpannedToken], i: usize) -> Result<f64, ParseError> {
+    let token = token_at(tokens, i, Expected::Float)?;
+    let PropertyValue::Float(value) = coerce(token, Expected::Float)? else {
+        unreachable!()
+    };
+    Ok(value)
+}
+
+/// Reads the origin named at `i`, which must already be declared in `rules`.
+fn declared_origin(
+    tokens: &[SpannedToken],
+    i: usize,
+    rules: &Ruleset,
+) -> Result<MagicType, ParseError> {
+    let token = token_at(tokens, i, Expected::MagicType)?;
+    let PropertyValue::MagicType(typ) = coerce(token, Expected::MagicType)? else {
+        unreachable!()
+    };
+    if rules.origin(&typ).is_none() {
+        return Err(ParseError::new(ParseErrorKind::UndeclaredOrigin, token));
+    }
+    Ok(typ)
+}
+
+fn apply_property(magic: &mut Magic, key: KeywordDef, value: PropertyValue) {
+    match (key, value) {
+        (KeywordDef::Rank, PropertyValue::Rank(rank)) => magic.rank = rank,
+        (KeywordDef::AlwaysDefense, PropertyValue::Bool(b)) => magic.always_def = b,
+        (KeywordDef::TableAddon, PropertyValue::Int(vl)) => magic.table_addon = vl,
+        (KeywordDef::RaceMult, PropertyValue::Float(vl)) => magic.race_mult = vl,
+        _ => unreachable!("coerce returns the type declared by KeywordDef::expected"),
+    }
+}
+
+/// The error for a token that can't start an entry of a block.
+fn unexpected_entry(token: &SpannedToken) -> ParseError {
+    let kind = match &token.token {
+        Token::Keyword(key) if key.is_property() => ParseErrorKind::PropertyOutsideBlock,
+        Token::Keyword(_) => ParseErrorKind::MisplacedKeyword,
+        Token::Name(_) => ParseErrorKind::UnknownIdentifier,
+        Token::Value(_) => ParseErrorKind::StrayValue,
+    };
+    ParseError::new(kind, token)
+}
+
+/// Fails if `key` was already set in the current block, otherwise remembers
+/// where it was set.
+fn check_duplicate<K: PartialEq>(
+    seen: &mut Vec<(K, Span)>,
+    key: K,
+    token: &SpannedToken,
+) -> Result<(), ParseError> {
+    if let Some((_, first)) = seen.iter().find(|(k, _)| *k == key) {
+        return Err(ParseError::new(
+            ParseErrorKind::DuplicateProperty {
+                line: first.line,
+                column: first.column,
+            },
+            token,
+        ));
+    }
+    seen.push((key, token.span));
+    Ok(())
+}
+
+/// Checks that the token at `i` opens a block.
+fn expect_block_start(tokens: &[SpannedToken], i: usize) -> Result<(), ParseError> {
+    let open = token_at(tokens, i, Expected::BlockStart)?;
+    if !matches!(open.token, Token::Keyword(KeywordDef::Start)) {
+        return Err(ParseError::new(ParseErrorKind::MissingBlockStart, open));
+    }
+    Ok(())
+}
+
+/// Walks the entries of a `{ ... }` block. `i` points at the opening brace
+/// and is left just past the closing one. `entry` reads the entry starting
+/// at `i` and moves `i` past it; braces are handled here.
+fn interpret_entries<F>(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    mut entry: F,
+) -> Result<(), ParseError>
+where
+    F: FnMut(&mut usize) -> Result<(), ParseError>,
+{
+    let open = &tokens[*i];
+    *i += 1;
+
+    loop {
+        let Some(current) = tokens.get(*i) else {
+            return Err(ParseError::new(ParseErrorKind::UnclosedBlock, open));
+        };
+
+        match &current.token {
+            Token::Keyword(KeywordDef::End) => {
+                *i += 1;
+                return Ok(());
+            }
+            Token::Keyword(KeywordDef::Register | KeywordDef::Rules) => {
+                return Err(ParseError::new(ParseErrorKind::UnclosedBlock, open));
+            }
+            Token::Keyword(KeywordDef::Start) => {
+                return Err(ParseError::new(ParseErrorKind::NestedBlock, current));
+            }
+            _ => entry(i)?,
+        }
+    }
+}
+
+/// Reads the properties of a `register` block. `i` points at the opening
+/// brace and is left just past the closing one.
+fn interpret_block(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    magic: &mut Magic,
+    rules: &Ruleset,
+) -> Result<(), ParseError> {
+    let mut seen: Vec<(KeywordDef, Span)> = Vec::new();
+
+    interpret_entries(tokens, i, |i| {
+        let current = &tokens[*i];
+        match &current.token {
+            Token::Keyword(KeywordDef::Type) => {
+                check_duplicate(&mut seen, KeywordDef::Type, current)?;
+                magic.typ = declared_origin(tokens, *i + 1, rules)?;
+                *i += 2;
+                Ok(())
+            }
+            Token::Keyword(KeywordDef::Accuracy) => {
+                check_duplicate(&mut seen, KeywordDef::Accuracy, current)?;
+                *i += 1;
+                magic.accuracy = Some(interpret_accuracy_curve(tokens, i)?);
+                Ok(())
+            }
+            Token::Keyword(key) if key.is_property() => {
+                check_duplicate(&mut seen, *key, current)?;
+                let next = token_at(tokens, *i + 1, key.expected())?;
+                apply_property(magic, *key, coerce(next, key.expected())?);
+                *i += 2;
+                Ok(())
+            }
+            _ => Err(unexpected_entry(current)),
+        }
+    })
+}
+
+/// Reads one row of multipliers, `{ <rank> <multiplier> ... }`, over
+/// `multipliers`. `i` points at the opening brace.
+fn interpret_multiplier_row(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    multipliers: &mut [f64; 6],
+) -> Result<(), ParseError> {
+    let mut seen_ranks: Vec<(MagicRank, Span)> = Vec::new();
+
+    interpret_entries(tokens, i, |i| {
+        let current = &tokens[*i];
+        let PropertyValue::Rank(rank) = coerce(current, Expected::Rank)? else {
+            unreachable!()
+        };
+        check_duplicate(&mut seen_ranks, rank, current)?;
+        multipliers[rank.index()] = float_at(tokens, *i + 1)?;
+        *i += 2;
+        Ok(())
+    })
+}
+
+/// Reads a `multipliers` block: one `<origin> { <rank> <multiplier> ... }`
+/// row per declared origin. `i` points at the opening brace.
+fn interpret_multipliers(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    rules: &mut Ruleset,
+) -> Result<(), ParseError> {
+    let mut seen_types: Vec<(MagicType, Span)> = Vec::new();
+
+    interpret_entries(tokens, i, |i| {
+        let typ = declared_origin(tokens, *i, rules)?;
+        check_duplicate(&mut seen_types, typ.clone(), &tokens[*i])?;
+        expect_block_start(tokens, *i + 1)?;
+        *i += 1;
+
+        let origin = rules.origin_mut(&typ).unwrap();
+        interpret_multiplier_row(tokens, i, &mut origin.multipliers)
+    })
+}
+
+/// Reads `= <formula>` starting at `i` and moves `i` past it. The formula
+/// runs to the end of the line or to a closing brace, and may only read
+/// `variables`.
+fn interpret_formula(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    variables: &'static [&'static str],
+) -> Result<Formula, ParseError> {
+    let equals = token_at(tokens, *i, Expected::Formula)?;
+    if equals.text != "=" {
+        return Err(unexpected(Expected::Formula, equals));
+    }
+    *i += 1;
+
+    let start = *i;
+    while let Some(token) = tokens.get(*i) {
+        if token.span.line != equals.span.line
+            || matches!(token.token, Token::Keyword(KeywordDef::End))
+        {
+            break;
+        }
+        *i += 1;
+    }
+    let words = &tokens[start..*i];
+    let (Some(first), Some(last)) = (words.first(), words.last()) else {
+        return Err(ParseError::new(
+            ParseErrorKind::InvalidFormula(Box::new(FormulaError::Syntax(
+                "the formula is empty".to_string(),
+            ))),
+            equals,
+        ));
+    };
+
+    let source = words
+        .iter()
+        .map(|w| w.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Formula::new(&source, variables).map_err(|e| {
+        let span = Span {
+            len: last.span.column + last.span.len - first.span.column,
+            ..first.span
+        };
+        ParseError::at(ParseErrorKind::InvalidFormula(Box::new(e)), span, &source)
+    })
+}
+
+/// Reads an accuracy curve starting at `i` and moves `i` past it. One of
+/// `linear <base> <slope>`, `logistic <min> <max> <midpoint> <steepness>`,
+/// `steps { <accuracy> <factor> ... }`, `parity { even <curve> odd <curve> }`,
+/// `capped <max> <curve>` or `= <formula>`. A side left out of a `parity` block keeps the
+/// default linear curve.
+fn interpret_accuracy_curve(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+) -> Result<AccuracyCurve, ParseError> {
+    let current = token_at(tokens, *i, Expected::AccuracyCurve)?;
+    match &current.token {
+        Token::Name(n) if n == "linear" => {
+            let base = float_at(tokens, *i + 1)?;
+            let slope = float_at(tokens, *i + 2)?;
+            *i += 3;
+            Ok(AccuracyCurve::Linear { base, slope })
+        }
+        Token::Name(n) if n == "logistic" => {
+            let curve = AccuracyCurve::Logistic {
+                min: float_at(tokens, *i + 1)?,
+                max: float_at(tokens, *i + 2)?,
+                midpoint: float_at(tokens, *i + 3)?,
+                steepness: float_at(tokens, *i + 4)?,
+            };
+            *i += 5;
+            Ok(curve)
+        }
+        Token::Name(n) if n == "steps" => {
+            expect_block_start(tokens, *i + 1)?;
+            *i += 1;
+
+            let mut steps: Vec<(i64, f64)> = Vec::new();
+            let mut seen: Vec<(i64, Span)> = Vec::new();
+            interpret_entries(tokens, i, |i| {
+                let from_token = &tokens[*i];
+                let PropertyValue::Int(from) = coerce(from_token, Expected::Int)? else {
+                    unreachable!()
+                };
+                check_duplicate(&mut seen, from, from_token)?;
+                steps.push((from, float_at(tokens, *i + 1)?));
+                *i += 2;
+                Ok(())
+            })?;
+
+            if steps.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::EmptySteps, current));
+            }
+            steps.sort_by_key(|&(from, _)| from);
+            Ok(AccuracyCurve::Steps(steps))
+        }
+        Token::Name(n) if n == "=" => Ok(AccuracyCurve::Expression(interpret_formula(
+            tokens,
+            i,
+            ACCURACY_VARIABLES,
+        )?)),
+        Token::Name(n) if n == "capped" => {
+            let max = float_at(tokens, *i + 1)?;
+            *i += 2;
+            Ok(AccuracyCurve::Capped {
+                max,
+                curve: Box::new(interpret_accuracy_curve(tokens, i)?),
+            })
+        }
+        Token::Name(n) if n == "parity" => {
+            expect_block_start(tokens, *i + 1)?;
+            *i += 1;
+
+            let mut even = AccuracyCurve::default();
+            let mut odd = AccuracyCurve::default();
+            let mut seen: Vec<(bool, Span)> = Vec::new();
+            interpret_entries(tokens, i, |i| {
+                let current = &tokens[*i];
+                let side = match &current.token {
+                    Token::Name(n) if n == "even" => &mut even,
+                    Token::Name(n) if n == "odd" => &mut odd,
+                    _ => return Err(unexpected_entry(current)),
+                };
+                check_duplicate(&mut seen, current.text == "even", current)?;
+                *i += 1;
+                *side = interpret_accuracy_curve(tokens, i)?;
+                Ok(())
+            })?;
+
+            Ok(AccuracyCurve::Parity {
+                even: Box::new(even),
+                odd: Box::new(odd),
+            })
+        }
+        _ => Err(unexpected(Expected::AccuracyCurve, current)),
+    }
+}
+
+/// Reads a defense formula starting at `i` and moves `i` past it:
+/// `scaled <factor>`, `scaled_log <factor>` or `= <formula>`.
+fn interpret_defense_formula(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+) -> Result<DefenseFormula, ParseError> {
+    let current = token_at(tokens, *i, Expected::DefenseFormula)?;
+    let formula = match &current.token {
+        Token::Name(n) if n == "=" => {
+            return Ok(DefenseFormula::Expression(interpret_formula(
+                tokens,
+                i,
+                DEFENSE_VARIABLES,
+            )?))
+        }
+        Token::Name(n) if n == "scaled" => DefenseFormula::Scaled {
+            factor: float_at(tokens, *i + 1)?,
+        },
+        Token::Name(n) if n == "scaled_log" => DefenseFormula::ScaledLog {
+            factor: float_at(tokens, *i + 1)?,
+        },
+        _ => return Err(unexpected(Expected::DefenseFormula, current)),
+    };
+    *i += 2;
+    Ok(formula)
+}
+
+/// Reads an `origin <name> { ... }` entry, declaring the origin if it is new
+/// and overriding the parts given otherwise. `i` points at `origin`.
+fn interpret_origin(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    rules: &mut Ruleset,
+) -> Result<(), ParseError> {
+    let next = token_at(tokens, *i + 1, Expected::Name)?;
+    let Token::Name(n) = &next.token else {
+        return Err(unexpected(Expected::Name, next));
+    };
+    let typ = MagicType::new(n);
+    expect_block_start(tokens, *i + 2)?;
+    *i += 2;
+
+    let origin = rules.declare_origin(&typ);
+    let mut seen: Vec<(KeywordDef, Span)> = Vec::new();
+    interpret_entries(tokens, i, |i| {
+        let current = &tokens[*i];
+        match &current.token {
+            Token::Keyword(KeywordDef::Multipliers) => {
+                check_duplicate(&mut seen, KeywordDef::Multipliers, current)?;
+                expect_block_start(tokens, *i + 1)?;
+                *i += 1;
+                interpret_multiplier_row(tokens, i, &mut origin.multipliers)
+            }
+            Token::Keyword(KeywordDef::Accuracy) => {
+                check_duplicate(&mut seen, KeywordDef::Accuracy, current)?;
+                *i += 1;
+                origin.accuracy = interpret_accuracy_curve(tokens, i)?;
+                Ok(())
+            }
+            Token::Keyword(KeywordDef::Damage) => {
+                check_duplicate(&mut seen, KeywordDef::Damage, current)?;
+                *i += 1;
+                origin.damage =
+                    DamageFormula::Expression(interpret_formula(tokens, i, DAMAGE_VARIABLES)?);
+                Ok(())
+            }
+            Token::Keyword(KeywordDef::Defense) => {
+                check_duplicate(&mut seen, KeywordDef::Defense, current)?;
+                *i += 1;
+                origin.defense = interpret_defense_formula(tokens, i)?;
+                Ok(())
+            }
+            _ => Err(unexpected_entry(current)),
+        }
+    })
+}
+
+/// Reads a `rules` block into `rules`. `i` points at the opening brace.
+fn interpret_rules(
+    tokens: &[SpannedToken],
+    i: &mut usize,
+    rules: &mut Ruleset,
+) -> Result<(), ParseError> {
+    let mut seen: Vec<(KeywordDef, Span)> = Vec::new();
+    let mut seen_origins: Vec<(MagicType, Span)> = Vec::new();
+
+    interpret_entries(tokens, i, |i| {
+        let current = &tokens[*i];
+        match &current.token {
+            Token::Keyword(KeywordDef::Multipliers) => {
+                check_duplicate(&mut seen, KeywordDef::Multipliers, current)?;
+                expect_block_start(tokens, *i + 1)?;
+                *i += 1;
+                interpret_multipliers(tokens, i, rules)
+            }
+            Token::Keyword(KeywordDef::Origin) => {
+                let name = token_at(tokens, *i + 1, Expected::Name)?;
+                check_duplicate(&mut seen_origins, MagicType::new(&name.text), name)?;
+                interpret_origin(tokens, i, rules)
+            }
+            _ => Err(unexpected_entry(current)),
+        }
+    })
+}
+
+fn interpret_tokens_to_magic(
+    tokens: &[SpannedToken],
+    rules: &mut Ruleset,
+) -> Result<Vec<Magic>, ParseError> {
     let mut registered_magics: Vec<Magic> = Vec::new();
     let mut i = 0;
 
     while i < tokens.len() {
-        match &tokens[i] {
-            Token::Keyword(key) => match key {
-                KeywordDef::Register => {
-                    registered_magics.push(Magic::new());
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Name(n) = &tokens[i + 1] {
-                        let t = registered_magics.len() - 1;
-                        registered_magics[t].name = n.clone();
-                    } else {
-                        return Err("Expected Name but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                KeywordDef::Rank => {
-                    if registered_magics.is_empty() {
-                        return Err("No registered magics!");
-                    }
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Value(v) = &tokens[i + 1] {
-                        if let ValueType::Int(vl) = v {
-                            let t = registered_magics.len() - 1;
-                            registered_magics[t].rank = i_to_magic_rank(*vl);
-                        } else {
-                            return Err("Expected Int but found something else!");
-                        }
-                    } else {
-                        return Err("Expected Int but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                KeywordDef::Type => {
-                    if registered_magics.is_empty() {
-                        return Err("No registered magics!");
-                    }
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Value(v) = &tokens[i + 1] {
-                        if let ValueType::Custom(c) = v {
-                            match c {
-                                CustomIdentifier::ORDER => {
-                                    let t = registered_magics.len() - 1;
-                                    registered_magics[t].typ = MagicType::ORDER;
-                                }
-                                CustomIdentifier::CHAOS => {
-                                    let t = registered_magics.len() - 1;
-                                    registered_magics[t].typ = MagicType::CHAOS;
-                                }
-                                _ => {}
-                            }
-                        } else {
-                            return Err("Expected ORDER/CHAOS but found something else!");
-                        }
-                    } else {
-                        return Err("Expected ORDER/CHAOS but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                KeywordDef::AlwaysDefense => {
-                    if registered_magics.is_empty() {
-                        return Err("No registered magics!");
-                    }
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Value(v) = &tokens[i + 1] {
-                        if let ValueType::Bool(b) = v {
-                            let t = registered_magics.len() - 1;
-                            registered_magics[t].always_def = *b;
-                        } else {
-                            return Err("Expected Bool but found something else!");
-                        }
-                    } else {
-                        return Err("Expected Bool but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                KeywordDef::TableAddon => {
-                    if registered_magics.is_empty() {
-                        return Err("No registered magics!");
-                    }
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Value(v) = &tokens[i + 1] {
-                        if let ValueType::Int(vl) = v {
-                            let t = registered_magics.len() - 1;
-                            registered_magics[t].table_addon = *vl;
-                        } else {
-                            return Err("Expected Int but found something else!");
-                        }
-                    } else {
-                        return Err("Expected Int but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                KeywordDef::RaceMult => {
-                    if registered_magics.is_empty() {
-                        return Err("No registered magics!");
-                    }
-
-                    if is_out_of_bounds(tokens, i + 1) {
-                        return Err("Out of bounds!");
-                    }
-
-                    if let Token::Value(v) = &tokens[i + 1] {
-                        if let ValueType::Float(vl) = v {
-                            let t: usize = registered_magics.len() - 1;
-                            registered_magics[t].race_mult = *vl;
-                        } else {
-                            return Err("Expected Int but found something else!");
-                        }
-                    } else {
-                        return Err("Expected Int but found something else!");
-                    }
-                    i += 2;
-                    continue;
-                }
-                _ => {}
-            },
-            Token::Name(name) => {}
-            Token::Value(value) => {}
-        }
+        let current = &tokens[i];
+        match &current.token {
+            Token::Keyword(KeywordDef::Register) => {
+                let next = token_at(tokens, i + 1, Expected::Name)?;
+                let Token::Name(n) = &next.token else {
+                    return Err(unexpected(Expected::Name, next));
+                };
+                expect_block_start(tokens, i + 2)?;
 
-        i += 1;
+                let mut magic = Magic::new(n.clone());
+                i += 2;
+                interpret_block(tokens, &mut i, &mut magic, rules)?;
+                registered_magics.push(magic);
+            }
+            Token::Keyword(KeywordDef::Rules) => {
+                expect_block_start(tokens, i + 1)?;
+                i += 1;
+                interpret_rules(tokens, &mut i, rules)?;
+            }
+            Token::Keyword(KeywordDef::Start) => {
+                return Err(ParseError::new(ParseErrorKind::NestedBlock, current));
+            }
+            Token::Keyword(KeywordDef::End) => {
+                return Err(ParseError::new(ParseErrorKind::UnmatchedBlockEnd, current));
+            }
+            _ => return Err(unexpected_entry(current)),
+        }
     }
 
     Ok(registered_magics)
@@ -285,12 +1040,628 @@ fn load_file(filename: &str) -> Result<String, std::io::Error> {
     fs::read_to_string(filename)
 }
 
-pub fn process_file_to_magic(filename: &str) -> Result<Vec<Magic>, &'static str> {
+/// Parses the contents of an init file, applying its `rules` blocks on top
+/// of `rules`. `filename` is only used in errors.
+pub fn parse_file(
+    content: &str,
+    filename: &str,
+    rules: &mut Ruleset,
+) -> Result<Vec<Magic>, ParseError> {
+    let vec_str = parse_string(content).map_err(|e| e.with_source(filename, content))?;
+    let tokens = parse_tokens(&vec_str);
+    interpret_tokens_to_magic(&tokens, rules).map_err(|e| e.with_source(filename, content))
+}
+
+/// Loads an init file, applying its `rules` blocks on top of `rules`.
+pub fn process_file(filename: &str, rules: &mut Ruleset) -> Result<Vec<Magic>, ParseError> {
     let content = match load_file(filename) {
         Ok(t) => t,
-        Err(e) => return Err("The file innit.rpg doesn't exist!"),
+        Err(e) => return Err(ParseError::io(filename, e)),
     };
-    let vec_str = parse_string(&content);
-    let tokens = parse_tokens(&vec_str);
-    interpret_tokens_to_magic(&tokens)
+    parse_file(&content, filename, rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &str = "register fire
+{
+    rank 1
+    type CHAOS
+    always_def false
+    table_addon 2
+    race_mult 1.2
+}
+register ice
+{
+    rank 0
+    type ORDER
+    always_def true
+    table_addon 0
+    race_mult 1.0
+}
+";
+
+    /// Parses `content` on top of the default rules.
+    fn parse_magics(content: &str, filename: &str) -> Result<Vec<Magic>, ParseError> {
+        parse_file(content, filename, &mut Ruleset::default())
+    }
+
+    fn plain() -> Vec<Magic> {
+        parse_magics(PLAIN, "plain.rpg").unwrap()
+    }
+
+    #[test]
+    fn line_comments_are_ignored() {
+        let commented = "# spells for the campaign
+register fire # main damage spell
+{
+    rank 1 # Uncommon
+    type CHAOS
+    always_def false
+    table_addon 2
+    race_mult 1.2 # elf bonus
+}
+#register thunder
+register ice
+{
+    rank 0
+    type ORDER
+    always_def true
+    table_addon 0
+    race_mult 1.0#no bonus
+}
+";
+        assert_eq!(parse_magics(commented, "commented.rpg").unwrap(), plain());
+    }
+
+    #[test]
+    fn block_comments_are_ignored() {
+        let commented = "/* spells for
+   the campaign */
+register fire
+{
+    rank /* Uncommon */ 1
+    type CHAOS
+    always_def false
+    table_addon 2
+    race_mult 1.2
+}
+/*
+register thunder
+{
+    rank 5
+}
+*/
+register ice/* inline */{
+    rank 0
+    type ORDER
+    always_def true
+    table_addon 0
+    race_mult 1.0
+}
+";
+        assert_eq!(parse_magics(commented, "commented.rpg").unwrap(), plain());
+    }
+
+    #[test]
+    fn comment_markers_inside_block_comments() {
+        let commented = format!("/* # not a line comment /* still inside */\n{}", PLAIN);
+        assert_eq!(parse_magics(&commented, "commented.rpg").unwrap(), plain());
+    }
+
+    #[test]
+    fn unterminated_block_comment_is_an_error() {
+        let err = parse_magics("register fire\n/* oops\n", "bad.rpg").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnterminatedComment);
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn errors_show_file_line_and_column() {
+        let err = parse_magics("register fire\n{\n\trank\tUltra\n}\n", "tabs.rpg").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tabs.rpg:3:7: `Ultra` is not a rank, use 0-5 or Common, Uncommon, Epic, Legendary, Mythic, Divine"
+        );
+
+        let err = process_file("no/such/init.rpg", &mut Ruleset::default()).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("no/such/init.rpg: could not read file: "));
+        assert_eq!(err.snippet(), None);
+    }
+
+    #[test]
+    fn snippets_underline_the_token() {
+        // Tabs before the token are kept so that the carets line up.
+        let err = parse_magics("register fire\n{\n\trank\tUltra\n}\n", "tabs.rpg").unwrap_err();
+        assert_eq!(
+            err.snippet().unwrap(),
+            "  |\n3 | \trank\tUltra\n  | \t    \t^^^^^"
+        );
+
+        let content = format!(
+            "{}register fire\n{{\n    race_mult fast\n}}\n",
+            "\n".repeat(9)
+        );
+        let err = parse_magics(&content, "long.rpg").unwrap_err();
+        assert_eq!(
+            err.snippet().unwrap(),
+            "   |\n12 |     race_mult fast\n   |               ^^^^"
+        );
+
+        let err = parse_magics("register fire\n{\n    rank", "eof.rpg").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "eof.rpg:3:9: expected a rank, found end of file"
+        );
+        assert_eq!(err.snippet().unwrap(), "  |\n3 |     rank\n  |         ^");
+    }
+
+    fn error_at(content: &str) -> (ParseErrorKind, usize, usize) {
+        let err = parse_magics(content, "bad.rpg").unwrap_err();
+        (err.kind, err.line, err.column)
+    }
+
+    #[test]
+    fn register_needs_a_block() {
+        assert_eq!(
+            error_at("register fire\nrank 1\n"),
+            (ParseErrorKind::MissingBlockStart, 2, 1)
+        );
+    }
+
+    #[test]
+    fn unclosed_block_points_at_its_brace() {
+        assert_eq!(
+            error_at("register fire {\n rank 1\n"),
+            (ParseErrorKind::UnclosedBlock, 1, 15)
+        );
+        assert_eq!(
+            error_at("register fire {\nregister ice { }"),
+            (ParseErrorKind::UnclosedBlock, 1, 15)
+        );
+    }
+
+    #[test]
+    fn braces_must_be_balanced() {
+        assert_eq!(
+            error_at("register fire { }\n}"),
+            (ParseErrorKind::UnmatchedBlockEnd, 2, 1)
+        );
+        assert_eq!(
+            error_at("register fire { { } }"),
+            (ParseErrorKind::NestedBlock, 1, 17)
+        );
+    }
+
+    #[test]
+    fn properties_must_be_inside_a_block() {
+        assert_eq!(
+            error_at("register fire { }\nrank 1"),
+            (ParseErrorKind::PropertyOutsideBlock, 2, 1)
+        );
+    }
+
+    #[test]
+    fn unknown_identifiers_and_stray_values_are_rejected() {
+        assert_eq!(
+            error_at("register fire {\n  rnak 1\n}"),
+            (ParseErrorKind::UnknownIdentifier, 2, 3)
+        );
+        assert_eq!(
+            error_at("register fire {\n  rank 1 2\n}"),
+            (ParseErrorKind::StrayValue, 2, 10)
+        );
+    }
+
+    #[test]
+    fn duplicate_properties_are_rejected() {
+        assert_eq!(
+            error_at("register fire {\n  rank 1\n  rank 2\n}"),
+            (
+                ParseErrorKind::DuplicateProperty { line: 2, column: 3 },
+                3,
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn ranks_accept_numbers_and_names() {
+        let numbered = parse_magics("register fire { rank 3 }", "ranks.rpg").unwrap();
+        let named = parse_magics("register fire { rank Legendary }", "ranks.rpg").unwrap();
+        assert_eq!(numbered[0].rank, MagicRank::Legendary);
+        assert_eq!(numbered, named);
+    }
+
+    #[test]
+    fn out_of_range_ranks_are_rejected() {
+        assert_eq!(
+            error_at("register fire {\n  rank 7\n}"),
+            (ParseErrorKind::InvalidRank, 2, 8)
+        );
+        assert_eq!(
+            error_at("register fire {\n  rank -1\n}"),
+            (ParseErrorKind::InvalidRank, 2, 8)
+        );
+        assert_eq!(
+            error_at("register fire {\n  rank Rare\n}"),
+            (ParseErrorKind::InvalidRank, 2, 8)
+        );
+        assert_eq!(
+            error_at("register fire {\n  rank true\n}"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::Rank,
+                    found: Found::Bool
+                },
+                2,
+                8
+            )
+        );
+    }
+
+    #[test]
+    fn ints_widen_to_floats() {
+        let int = parse_magics("register fire { race_mult 2 }", "mult.rpg").unwrap();
+        let float = parse_magics("register fire { race_mult 2.0 }", "mult.rpg").unwrap();
+        assert_eq!(int[0].race_mult, 2.0);
+        assert_eq!(int, float);
+    }
+
+    #[test]
+    fn floats_do_not_narrow_to_ints() {
+        let err = parse_magics("register fire {\n  table_addon 1.5\n}", "addon.rpg").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Unexpected {
+                expected: Expected::Int,
+                found: Found::Float
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "addon.rpg:2:15: expected an Int, found the Float `1.5`"
+        );
+    }
+
+    #[test]
+    fn rules_override_multipliers() {
+        let mut rules = Ruleset::default();
+        let magics = parse_file(
+            "rules
+{
+    multipliers
+    {
+        ORDER { Common 4.5 Divine 30 }
+        CHAOS { 2 11 }
+    }
+}
+register fire { rank 0 }",
+            "rules.rpg",
+            &mut rules,
+        )
+        .unwrap();
+
+        let order = rules.origin(&MagicType::ORDER).unwrap();
+        let chaos = rules.origin(&MagicType::CHAOS).unwrap();
+        assert_eq!(magics.len(), 1);
+        assert_eq!(order.multiplier(MagicRank::Common), 4.5);
+        assert_eq!(order.multiplier(MagicRank::Divine), 30.0);
+        assert_eq!(chaos.multiplier(MagicRank::Epic), 11.0);
+        assert_eq!(order.multiplier(MagicRank::Uncommon), 6.0);
+        assert_eq!(chaos.multiplier(MagicRank::Common), 5.5);
+    }
+
+    #[test]
+    fn rules_declare_origins() {
+        let mut rules = Ruleset::default();
+        let magics = parse_file(
+            "rules
+{
+    origin VOID
+    {
+        multipliers { Common 5 Divine 40 }
+        accuracy parity { odd linear 0.4 0.03 }
+        defense scaled_log 2
+    }
+    origin ORDER { defense scaled 1.5 }
+    multipliers { VOID { Epic 12 } }
+}
+register rift { type VOID }",
+            "origins.rpg",
+            &mut rules,
+        )
+        .unwrap();
+
+        let void = rules.origin(&MagicType::new("VOID")).unwrap();
+        assert_eq!(magics[0].typ, MagicType::new("VOID"));
+        assert_eq!(void.multipliers, [5.0, 6.0, 12.0, 13.0, 18.0, 40.0]);
+        assert_eq!(
+            void.accuracy,
+            AccuracyCurve::Parity {
+                even: Box::default(),
+                odd: Box::new(AccuracyCurve::Linear {
+                    base: 0.4,
+                    slope: 0.03
+                }),
+            }
+        );
+        assert_eq!(void.defense, DefenseFormula::ScaledLog { factor: 2.0 });
+        assert_eq!(
+            rules.origin(&MagicType::ORDER).unwrap().defense,
+            DefenseFormula::Scaled { factor: 1.5 }
+        );
+    }
+
+    #[test]
+    fn undeclared_origins_are_rejected() {
+        let err = parse_magics("register rift {\n  type VOID\n}", "rift.rpg").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UndeclaredOrigin);
+        assert_eq!(
+            err.to_string(),
+            "rift.rpg:2:8: `VOID` is not a declared origin, declare it first with `origin VOID { ... }` in a `rules` block"
+        );
+        assert_eq!(
+            error_at("rules { multipliers { VOID { 0 4 } } }"),
+            (ParseErrorKind::UndeclaredOrigin, 1, 23)
+        );
+        assert_eq!(
+            error_at("register rift { type VOID }\nrules { origin VOID { } }"),
+            (ParseErrorKind::UndeclaredOrigin, 1, 22)
+        );
+    }
+
+    #[test]
+    fn magics_override_the_accuracy_curve() {
+        let magics = parse_magics(
+            "register fire { accuracy capped 1.2 logistic 0.5 1.5 10 0.3 }
+register ice { accuracy steps { 10 0.8 0 0.5 15 1 } }
+register wind { }",
+            "curves.rpg",
+        )
+        .unwrap();
+
+        assert_eq!(
+            magics[0].accuracy,
+            Some(AccuracyCurve::Capped {
+                max: 1.2,
+                curve: Box::new(AccuracyCurve::Logistic {
+                    min: 0.5,
+                    max: 1.5,
+                    midpoint: 10.0,
+                    steepness: 0.3
+                }),
+            })
+        );
+        assert_eq!(
+            magics[1].accuracy,
+            Some(AccuracyCurve::Steps(vec![(0, 0.5), (10, 0.8), (15, 1.0)]))
+        );
+        assert_eq!(magics[2].accuracy, None);
+    }
+
+    #[test]
+    fn accuracy_curve_errors() {
+        assert_eq!(
+            error_at("register fire {\n  accuracy steps { }\n}"),
+            (ParseErrorKind::EmptySteps, 2, 12)
+        );
+        assert_eq!(
+            error_at("register fire { accuracy steps { 1 0.5 1 0.6 } }"),
+            (
+                ParseErrorKind::DuplicateProperty {
+                    line: 1,
+                    column: 34
+                },
+                1,
+                40
+            )
+        );
+        assert_eq!(
+            error_at("register fire { accuracy steps { 1.5 0.5 } }"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::Int,
+                    found: Found::Float
+                },
+                1,
+                34
+            )
+        );
+        assert_eq!(
+            error_at("register fire { accuracy logistic 0.5 1.5 10 }"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::Float,
+                    found: Found::Brace
+                },
+                1,
+                46
+            )
+        );
+    }
+
+    #[test]
+    fn origins_take_formulas() {
+        let mut rules = Ruleset::default();
+        parse_file(
+            "rules
+{
+    origin VOID
+    {
+        accuracy = 0.5 + accuracy * 0.03
+        damage = floor(mana * rank_mult * accuracy * race_mult)
+        defense = damage * 2 }
+}",
+            "formulas.rpg",
+            &mut rules,
+        )
+        .unwrap();
+
+        let void = rules.origin(&MagicType::new("VOID")).unwrap();
+        let AccuracyCurve::Expression(accuracy) = &void.accuracy else {
+            panic!("expected a formula, got {:?}", void.accuracy)
+        };
+        let DamageFormula::Expression(damage) = &void.damage else {
+            panic!("expected a formula, got {:?}", void.damage)
+        };
+        let DefenseFormula::Expression(defense) = &void.defense else {
+            panic!("expected a formula, got {:?}", void.defense)
+        };
+        assert_eq!(accuracy.source(), "0.5 + accuracy * 0.03");
+        assert_eq!(
+            damage.source(),
+            "floor(mana * rank_mult * accuracy * race_mult)"
+        );
+        assert_eq!(defense.source(), "damage * 2");
+    }
+
+    #[test]
+    fn formulas_need_no_space_around_the_equals_sign() {
+        let mut rules = Ruleset::default();
+        parse_file(
+            "rules { origin VOID {
+                accuracy=if(accuracy >= 10, 1.0, 0.5)
+                damage =mana*2
+                defense= if(damage == 0, 1, damage) } }",
+            "formulas.rpg",
+            &mut rules,
+        )
+        .unwrap();
+
+        let void = rules.origin(&MagicType::new("VOID")).unwrap();
+        let AccuracyCurve::Expression(accuracy) = &void.accuracy else {
+            panic!("expected a formula, got {:?}", void.accuracy)
+        };
+        let DamageFormula::Expression(damage) = &void.damage else {
+            panic!("expected a formula, got {:?}", void.damage)
+        };
+        let DefenseFormula::Expression(defense) = &void.defense else {
+            panic!("expected a formula, got {:?}", void.defense)
+        };
+        assert_eq!(accuracy.source(), "if(accuracy >= 10, 1.0, 0.5)");
+        assert_eq!(damage.source(), "mana*2");
+        assert_eq!(defense.source(), "if(damage == 0, 1, damage)");
+    }
+
+    #[test]
+    fn malformed_formulas_are_reported_when_loading() {
+        let err = parse_magics(
+            "rules {\n  origin VOID { damage = mana * (rank_mult }\n}",
+            "bad.rpg",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            &err.kind,
+            ParseErrorKind::InvalidFormula(e) if matches!(**e, FormulaError::Syntax(_))
+        ));
+        assert_eq!((err.line, err.column, err.len), (2, 26, 17));
+
+        let err =
+            parse_magics("rules { origin VOID { damage = mana * power } }", "bad.rpg").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bad.rpg:1:32: invalid formula `mana * power`: unknown variable `power`, use one of mana, accuracy, rank_mult, race_mult"
+        );
+
+        let err =
+            parse_magics("register fire { accuracy = sqrt(accuracy) }", "bad.rpg").unwrap_err();
+        assert!(matches!(
+            &err.kind,
+            ParseErrorKind::InvalidFormula(e) if matches!(**e, FormulaError::Eval(_))
+        ));
+
+        assert_eq!(
+            error_at("rules { origin VOID { damage mana } }"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::Formula,
+                    found: Found::Name
+                },
+                1,
+                30
+            )
+        );
+    }
+
+    #[test]
+    fn origin_errors() {
+        assert_eq!(
+            error_at("rules {\n  origin VOID { accuracy cubic 1 }\n}"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::AccuracyCurve,
+                    found: Found::Name
+                },
+                2,
+                26
+            )
+        );
+        assert_eq!(
+            error_at("rules { origin VOID { defense scaled } }"),
+            (
+                ParseErrorKind::Unexpected {
+                    expected: Expected::Float,
+                    found: Found::Brace
+                },
+                1,
+                38
+            )
+        );
+        assert_eq!(
+            error_at("rules { origin VOID { } origin VOID { } }"),
+            (
+                ParseErrorKind::DuplicateProperty {
+                    line: 1,
+                    column: 16
+                },
+                1,
+                32
+            )
+        );
+        assert_eq!(
+            error_at(
+                "rules { origin VOID { accuracy parity { even linear 1 0 even linear 1 0 } } }"
+            ),
+            (
+                ParseErrorKind::DuplicateProperty {
+                    line: 1,
+                    column: 41
+                },
+                1,
+                57
+            )
+        );
+    }
+
+    #[test]
+    fn rules_errors() {
+        assert_eq!(
+            error_at("rules { multipliers { ORDER { 0 4 Common 5 } } }"),
+            (
+                ParseErrorKind::DuplicateProperty {
+                    line: 1,
+                    column: 31
+                },
+                1,
+                35
+            )
+        );
+        assert_eq!(
+            error_at("rules { multipliers { ORDER { 6 4 } } }"),
+            (ParseErrorKind::InvalidRank, 1, 31)
+        );
+        assert_eq!(
+            error_at("register fire { multipliers { } }"),
+            (ParseErrorKind::MisplacedKeyword, 1, 17)
+        );
+        assert_eq!(
+            error_at("rules { rank 1 }"),
+            (ParseErrorKind::PropertyOutsideBlock, 1, 9)
+        );
+    }
 }
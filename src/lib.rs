@@ -0,0 +1,30 @@
+//! Calculator for the magic system of a homebrew RPG.
+//!
+//! The [`calc`] module holds the damage and defense formulas, which read
+//! their numbers from a [`Ruleset`]. [`handle_file`] reads magics and rules
+//! from `init.rpg` files and [`functions`] turns them into evalexpr
+//! functions, which is what the `rpgcalc` binary evaluates expressions
+//! against.
+//!
+//! ```
+//! use rpgcalc::{Magic, MagicRank, MagicType, Ruleset};
+//!
+//! let mut fire = Magic::new("fire");
+//! fire.rank = MagicRank::Uncommon;
+//! fire.typ = MagicType::ORDER;
+//! fire.race_mult = 1.2;
+//!
+//! assert_eq!(fire.damage(&Ruleset::default(), 10, 10), Ok(54));
+//! ```
+
+pub mod calc;
+pub mod functions;
+pub mod handle_file;
+pub mod magic;
+pub mod plot;
+pub mod rules;
+pub mod table;
+pub mod validate;
+
+pub use magic::{Magic, MagicRank, MagicType};
+pub use rules::{Origin, Ruleset};
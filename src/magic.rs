@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use evalexpr::{EvalexprError, EvalexprResult};
+
+use crate::calc::{calculate_defense_life, calculate_effective_damage};
+use crate::rules::{AccuracyCurve, Origin, Ruleset};
+
+/// Where a magic comes from, by name. ORDER and CHAOS are built in and more
+/// can be declared in `rules` blocks; each origin has its own multipliers,
+/// accuracy curve and defense formula in the [`Ruleset`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MagicType(Cow<'static, str>);
+
+impl MagicType {
+    pub const ORDER: MagicType = MagicType(Cow::Borrowed("ORDER"));
+    pub const CHAOS: MagicType = MagicType(Cow::Borrowed("CHAOS"));
+
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(Cow::Owned(name.into()))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MagicType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// How far a magic has been developed, from `Common` (rank 0) to `Divine`
+/// (rank 5).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MagicRank {
+    Common,
+    Uncommon,
+    Epic,
+    Legendary,
+    Mythic,
+    Divine,
+}
+
+impl MagicRank {
+    /// Every rank, from `Common` to `Divine`.
+    pub const ALL: [MagicRank; 6] = [
+        MagicRank::Common,
+        MagicRank::Uncommon,
+        MagicRank::Epic,
+        MagicRank::Legendary,
+        MagicRank::Mythic,
+        MagicRank::Divine,
+    ];
+
+    /// The number of the rank as written in init files, from 0 to 5.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A magic as declared by a `register` block in an init file.
+#[derive(Debug, PartialEq)]
+pub struct Magic {
+    pub name: String,
+    pub rank: MagicRank,
+    pub typ: MagicType,
+    /// Whether the magic is defensive by default, in which case its plain
+    /// name computes defense and `at_<name>` computes damage.
+    pub always_def: bool,
+    /// Added to the default accuracy of 10 when plotting tables.
+    pub table_addon: i64,
+    pub race_mult: f64,
+    /// Replaces the accuracy curve of the magic's origin.
+    pub accuracy: Option<AccuracyCurve>,
+}
+
+impl Magic {
+    /// A Common ORDER magic with no modifiers.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            rank: MagicRank::Common,
+            typ: MagicType::ORDER,
+            always_def: false,
+            table_addon: 0,
+            race_mult: 1.0,
+            accuracy: None,
+        }
+    }
+
+    /// The magic's origin in `rules`, with the magic's own accuracy curve in
+    /// place of the origin's if it has one. `None` if `rules` doesn't declare
+    /// the origin.
+    pub fn origin(&self, rules: &Ruleset) -> Option<Origin> {
+        let mut origin = rules.origin(&self.typ)?.clone();
+        if let Some(curve) = &self.accuracy {
+            origin.accuracy = curve.clone();
+        }
+        Some(origin)
+    }
+
+    fn declared_origin(&self, rules: &Ruleset) -> EvalexprResult<Origin> {
+        self.origin(rules).ok_or_else(|| {
+            EvalexprError::CustomMessage(format!("`{}` is not a declared origin", self.typ))
+        })
+    }
+
+    /// Damage dealt when cast with `accuracy` points and `mana`. Fails if
+    /// `rules` doesn't declare the magic's origin or one of its formulas
+    /// fails.
+    pub fn damage(&self, rules: &Ruleset, accuracy: i64, mana: i64) -> EvalexprResult<i64> {
+        let origin = self.declared_origin(rules)?;
+        calculate_effective_damage(&origin, accuracy, mana, self.rank, self.race_mult)
+    }
+
+    /// Life of the barrier raised when cast defensively with `accuracy`
+    /// points and `mana`. Fails like [`Magic::damage`].
+    pub fn defense(&self, rules: &Ruleset, accuracy: i64, mana: i64) -> EvalexprResult<i64> {
+        let origin = self.declared_origin(rules)?;
+        calculate_defense_life(&origin, accuracy, mana, self.rank, self.race_mult)
+    }
+}
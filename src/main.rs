@@ -1,433 +1,456 @@
 use crossterm::{
-    cursor, execute, queue,
+    queue,
     style::{self, Print, PrintStyledContent, Stylize},
-    terminal, ExecutableCommand, QueueableCommand,
 };
 use evalexpr::*;
-use handle_file::MagicRank;
-use handle_file::MagicType;
-use prettytable::row;
-use std::string::String;
-use std::{
-    fmt::Debug,
-    io::{stdout, Write},
-};
-
-pub mod handle_file;
-use handle_file::process_file_to_magic;
-use handle_file::Magic;
-
-#[inline]
-fn get_multiplier(rank: MagicRank, typ: MagicType) -> f64 {
-    match rank {
-        MagicRank::Common => match typ {
-            MagicType::ORDER => 4.0,
-            MagicType::CHAOS => 5.5,
-        },
-        MagicRank::Uncommon => match typ {
-            MagicType::ORDER => 6.0,
-            MagicType::CHAOS => 7.5,
-        },
-        MagicRank::Epic => match typ {
-            MagicType::ORDER => 9.0,
-            MagicType::CHAOS => 10.5,
-        },
-        MagicRank::Legendary => match typ {
-            MagicType::ORDER => 13.0,
-            MagicType::CHAOS => 14.5,
-        },
-        MagicRank::Mythic => match typ {
-            MagicType::ORDER => 18.0,
-            MagicType::CHAOS => 19.5,
-        },
-        MagicRank::Divine => match typ {
-            MagicType::ORDER => 24.0,
-            MagicType::CHAOS => 25.5,
-        },
+use rpgcalc::functions::{register_magics, reload_magics, signatures, CALCULATOR_FUNCTIONS};
+use rpgcalc::handle_file::{process_file, ParseError};
+use rpgcalc::table::{SharedTableOutput, TableFormat, TableOutput};
+use rpgcalc::validate::{validate_magics, EVALEXPR_BUILTINS};
+use rpgcalc::{Magic, Ruleset};
+use std::fs::File;
+use std::io::{stderr, stdin, stdout, BufReader, IsTerminal, Stdout, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+mod cli;
+mod commands;
+mod editor;
+mod script;
+mod session;
+mod watch;
+use cli::{parse_args, Command, Options};
+use commands::{magic_table, variable_table, MetaCommand};
+use editor::{Editor, History, Input};
+use script::run_script;
+use session::Entry;
+use watch::Watcher;
+
+/// Loads the rules files and then the init files, in the order given.
+fn load_magics(options: &Options) -> Result<(Vec<Magic>, Ruleset), ParseError> {
+    let mut rules = Ruleset::default();
+    let mut magics = Vec::new();
+    for file in options.rules_files.iter().chain(&options.init_files) {
+        magics.extend(process_file(file, &mut rules)?);
     }
+    Ok((magics, rules))
 }
 
-#[inline]
-fn calculate_total_damage(mana: i64, rank: MagicRank, typ: MagicType) -> f64 {
-    (mana as f64) * get_multiplier(rank, typ).floor()
-}
-
-#[inline]
-fn calculate_accuracy(accr: i64, typ: MagicType) -> f64 {
-    let t: f64 = accr as f64;
-    match typ {
-        MagicType::ORDER => 0.5 + t * 0.025,
-        MagicType::CHAOS => {
-            if accr % 2 == 0 {
-                0.5 + t * 0.025
-            } else {
-                0.5 + t * 0.18 / 8.0
+/// Loads the magics and checks them, printing what went wrong if anything
+/// did.
+fn load_checked_magics(options: &Options) -> Option<(Vec<Magic>, Ruleset)> {
+    let (magics, rules) = match load_magics(options) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            if let Some(snippet) = e.snippet() {
+                eprintln!("{}", snippet);
             }
+            return None;
         }
-    }
-}
+    };
 
-#[inline]
-fn calculate_effective_damage(
-    accr: i64,
-    mana: i64,
-    rank: MagicRank,
-    typ: MagicType,
-    mul: f64,
-) -> i64 {
-    (calculate_total_damage(mana, rank, typ) * calculate_accuracy(accr, typ) * mul).floor() as i64
-}
+    if let Err(errors) = validate_magics(&magics) {
+        eprintln!(
+            "ERROR: found {} problem(s) with the loaded magics:",
+            errors.len()
+        );
+        for e in errors {
+            eprintln!("  - {}", e);
+        }
+        return None;
+    }
 
-#[inline]
-fn calculate_effective_damage_f64(
-    accr: i64,
-    mana: i64,
-    rank: MagicRank,
-    typ: MagicType,
-    mul: f64,
-) -> f64 {
-    (calculate_total_damage(mana, rank, typ) * calculate_accuracy(accr, typ) * mul).floor()
+    Some((magics, rules))
 }
 
-#[inline]
-fn calculate_defense_life(accr: i64, mana: i64, rank: MagicRank, typ: MagicType, mul: f64) -> i64 {
-    let r = calculate_effective_damage_f64(accr, mana, rank, typ, mul);
-    match typ {
-        MagicType::ORDER => (1.3 * r) as i64,
-        MagicType::CHAOS => ((1.3 * r) - r.ln()) as i64,
+/// Prints an error the way the REPL does.
+fn print_repl_error(stdout: &mut Stdout, color: bool, message: &str) {
+    if color {
+        queue!(
+            stdout,
+            PrintStyledContent("····→ : ".red()),
+            PrintStyledContent(message.red()),
+            Print("\n")
+        )
+        .unwrap();
+    } else {
+        queue!(stdout, Print("····→ : "), Print(message), Print("\n")).unwrap();
     }
 }
 
-#[inline]
-fn default_help_message(
-    magic_name: &String,
-    always_defensive: bool,
-    should_be_defensive: bool,
-) -> String {
-    if !always_defensive {
-        if !should_be_defensive {
-            format!("Use {:}(<accuracy>, <mana>)", magic_name)
-        } else {
-            format!("Use def_{:}(<accuracy>, <mana>)", magic_name)
-        }
-    } else {
-        if !should_be_defensive {
-            format!("Use {:}(<accuracy>, <mana>)", magic_name)
-        } else {
-            format!("Use at_{:}(<accuracy>, <mana>)", magic_name)
-        }
+/// Loads the magics again and replaces the functions in `context` with
+/// theirs, see [`reload_magics`]. If the files have errors they are printed
+/// and nothing changes. Returns whether the magics were replaced.
+fn reload(
+    context: &mut HashMapContext,
+    magics: &mut Vec<Magic>,
+    output: &SharedTableOutput,
+    options: &Options,
+) -> bool {
+    let Ok(removed) = reload_magics(context, magics, output, || {
+        load_checked_magics(options).ok_or(())
+    }) else {
+        eprintln!("The magics loaded before are still in use.");
+        return false;
+    };
+
+    print!(
+        "Loaded {} magic(s) from {}.",
+        magics.len(),
+        options.init_files.join(", ")
+    );
+    if !removed.is_empty() {
+        print!(" Removed {}.", removed.join(", "));
     }
+    println!();
+    true
 }
 
-fn table_help_message(
-    magic_name: &String,
-    always_defensive: bool,
-    should_be_defensive: bool,
-) -> String {
-    if !always_defensive {
-        if !should_be_defensive {
-            format!(
-                "Use t_{:}(<start>, <end>, <?step>, <?accuracy>)",
-                magic_name
-            )
-        } else {
-            format!(
-                "Use t_def_{:}(<start>, <end>, <?step>, <?accuracy>)",
-                magic_name
-            )
-        }
-    } else {
-        if !should_be_defensive {
-            format!(
-                "Use t_{:}(<start>, <end>, <?step>, <?accuracy>)",
-                magic_name
-            )
-        } else {
-            format!(
-                "Use t_at_{:}(<start>, <end>, <?step>, <?accuracy>)",
-                magic_name
-            )
-        }
-    }
+/// The names Tab completes to, with the usage of the magic functions.
+fn function_completions(magics: &[Magic]) -> Vec<(String, Option<String>)> {
+    signatures(magics)
+        .into_iter()
+        .map(|(name, usage)| (name, Some(usage)))
+        .chain(
+            EVALEXPR_BUILTINS
+                .iter()
+                .filter(|name| !CALCULATOR_FUNCTIONS.contains(name))
+                .map(|name| (name.to_string(), None)),
+        )
+        .collect()
 }
 
-fn generate_default_function(should_be_defensive: bool, mag: &Magic) -> Function {
-    let rank = mag.rank.clone();
-    let typ = mag.typ.clone();
-    let mult = mag.race_mult;
-    let name = mag.name.clone();
-    let always_defensive = mag.always_def;
-    Function::new(move |arguments: &Value| {
-        let args = if let Ok(result) = arguments.as_tuple() {
-            result
-        } else {
-            return Err(EvalexprError::CustomMessage(default_help_message(
-                &name,
-                always_defensive,
-                should_be_defensive,
-            )));
-        };
+fn repl(
+    context: &mut HashMapContext,
+    mut magics: Vec<Magic>,
+    output: &SharedTableOutput,
+    options: &Options,
+) {
+    let blue = style::Color::Rgb {
+        r: 115,
+        g: 170,
+        b: 218,
+    };
+    let orange = style::Color::Rgb {
+        r: 255,
+        g: 188,
+        b: 65,
+    };
 
-        if args.len() != 2 {
-            return Err(EvalexprError::CustomMessage(default_help_message(
-                &name,
-                always_defensive,
-                should_be_defensive,
-            )));
+    let history = History::default_path()
+        .map(History::load)
+        .unwrap_or_default();
+    let mut editor = if options.color {
+        let cyan = style::Color::Rgb {
+            r: 137,
+            g: 221,
+            b: 255,
         };
+        Editor::new(history, ">>> ", Some(cyan), Some(orange))
+    } else {
+        Editor::new(history, ">>> ", None, None)
+    };
+    editor.completions.functions = function_completions(&magics);
+    if options.watch {
+        editor.watcher = Some(Watcher::new(
+            options.rules_files.iter().chain(&options.init_files),
+        ));
+    }
+    let mut stdout = stdout();
 
-        if let (Value::Int(accuracy), Value::Int(mana)) = (&args[0], &args[1]) {
-            if !should_be_defensive {
-                Ok(Value::Int(calculate_effective_damage(
-                    *accuracy, *mana, rank, typ, mult,
-                )))
-            } else {
-                Ok(Value::Int(calculate_defense_life(
-                    *accuracy, *mana, rank, typ, mult,
-                )))
+    let mut transcript: Vec<Entry> = Vec::new();
+    // Turned off when the session file can't be loaded, so closing the
+    // calculator doesn't overwrite it with an empty session.
+    let mut autosave = true;
+    if let Some(path) = &options.session {
+        if Path::new(path).exists() {
+            match session::load(path, context) {
+                Ok(entries) => {
+                    transcript = entries;
+                    if !options.quiet {
+                        println!(
+                            "Restored {} variable(s) from {}.",
+                            context.iter_variable_names().count(),
+                            path
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("ERROR: {}", e);
+                    eprintln!(
+                        "WARNING: {} won't be saved on exit, use `:save` to overwrite it",
+                        path
+                    );
+                    autosave = false;
+                }
             }
-        } else {
-            Err(EvalexprError::CustomMessage(default_help_message(
-                &name,
-                always_defensive,
-                should_be_defensive,
-            )))
         }
-    })
-}
-
-fn generate_default_table_function(should_be_defensive: bool, mag: &Magic) -> Function {
-    let rank = mag.rank.clone();
-    let typ = mag.typ.clone();
-    let mult = mag.race_mult;
-    let table_addon = mag.table_addon;
-    let name = mag.name.clone();
-    let always_defensive = mag.always_def;
-    Function::new(move |arguments| {
-        let args = if let Ok(result) = arguments.as_tuple() {
-            result
-        } else {
-            return Err(EvalexprError::CustomMessage(table_help_message(
-                &name,
-                always_defensive,
-                should_be_defensive,
-            )));
-        };
+    }
 
-        if args.len() != 2 && args.len() != 3 && args.len() != 4 {
-            return Err(EvalexprError::CustomMessage(table_help_message(
-                &name,
-                always_defensive,
-                should_be_defensive,
-            )));
+    loop {
+        editor.completions.variables = context.iter_variable_names().collect();
+        let inp = match editor.read_line() {
+            Ok(Input::Line(inp)) => inp,
+            Ok(Input::Eof) => break,
+            Ok(Input::FilesChanged) => {
+                if reload(context, &mut magics, output, options) {
+                    editor.completions.functions = function_completions(&magics);
+                }
+                continue;
+            }
+            Err(e) => {
+                eprintln!("ERROR: could not read the line: {}", e);
+                break;
+            }
         };
+        if inp.trim().is_empty() {
+            continue;
+        }
 
-        if args.len() == 2 {
-            if let (Value::Int(start), Value::Int(end)) = (&args[0], &args[1]) {
-                let mut table = prettytable::Table::new();
-
-                table.add_row(row!["Mana", "Damage", "Accuracy"]);
-
-                if !should_be_defensive {
-                    for i in *start..=*end {
-                        table.add_row(row![
-                            i,
-                            calculate_effective_damage(10 + table_addon, i, rank, typ, mult),
-                            10 + table_addon
-                        ]);
-                    }
+        match MetaCommand::parse(&inp) {
+            Some(Ok(MetaCommand::Help)) => println!("{}", commands::HELP),
+            Some(Ok(MetaCommand::List)) => {
+                magic_table(&magics).print(TableFormat::Terminal, options.color)
+            }
+            Some(Ok(MetaCommand::Vars)) => {
+                if context.iter_variable_names().next().is_none() {
+                    println!("No variables yet, set one with e.g. `a = 12`.");
                 } else {
-                    for i in *start..=*end {
-                        table.add_row(row![
-                            i,
-                            calculate_defense_life(10 + table_addon, i, rank, typ, mult),
-                            10 + table_addon
-                        ]);
-                    }
+                    variable_table(context).print(TableFormat::Terminal, options.color);
                 }
-
-                table.printstd();
-
-                Ok(Value::Empty)
-            } else {
-                Err(EvalexprError::CustomMessage(table_help_message(
-                    &name,
-                    always_defensive,
-                    should_be_defensive,
-                )))
             }
-        } else {
-            let accuracy: i64 = if args.len() == 4 {
-                if let (Value::Int(acc)) = &args[3] {
-                    *acc
-                } else {
-                    return Err(EvalexprError::CustomMessage(table_help_message(
-                        &name,
-                        always_defensive,
-                        should_be_defensive,
-                    )));
+            Some(Ok(MetaCommand::Clear)) => context.clear_variables(),
+            Some(Ok(MetaCommand::Reload)) => {
+                if reload(context, &mut magics, output, options) {
+                    editor.completions.functions = function_completions(&magics);
                 }
-            } else {
-                10 + table_addon
-            };
-
-            if let (Value::Int(start), Value::Int(end), Value::Int(step)) =
-                (&args[0], &args[1], &args[2])
-            {
-                let mut table = prettytable::Table::new();
-
-                table.add_row(row!["Mana", "Damage", "Accuracy"]);
-
-                if !should_be_defensive {
-                    for i in (*start..=*end).step_by(*step as usize) {
-                        table.add_row(row![
-                            i,
-                            calculate_effective_damage(accuracy, i, rank, typ, mult),
-                            accuracy
-                        ]);
+            }
+            Some(Ok(MetaCommand::Save(file))) => match file.as_ref().or(options.session.as_ref()) {
+                Some(path) => {
+                    if save_session(path, context, &transcript)
+                        && options.session.as_deref() == Some(path.as_str())
+                    {
+                        autosave = true;
                     }
-                } else {
-                    for i in (*start..=*end).step_by(*step as usize) {
-                        table.add_row(row![
-                            i,
-                            calculate_defense_life(accuracy, i, rank, typ, mult),
-                            accuracy
-                        ]);
+                }
+                None => print_repl_error(
+                    &mut stdout,
+                    options.color,
+                    "give a file to save to, e.g. `:save prep.calc`",
+                ),
+            },
+            Some(Ok(MetaCommand::Load(file))) => match file.as_ref().or(options.session.as_ref()) {
+                Some(path) => match session::load(path, context) {
+                    Ok(entries) => {
+                        transcript = entries;
+                        if options.session.as_deref() == Some(path.as_str()) {
+                            autosave = true;
+                        }
+                        println!(
+                            "Loaded {} variable(s) from {}.",
+                            context.iter_variable_names().count(),
+                            path
+                        );
+                    }
+                    Err(e) => print_repl_error(&mut stdout, options.color, &e),
+                },
+                None => print_repl_error(
+                    &mut stdout,
+                    options.color,
+                    "give a file to load, e.g. `:load prep.calc`",
+                ),
+            },
+            Some(Ok(MetaCommand::Quit)) => break,
+            Some(Err(e)) => print_repl_error(&mut stdout, options.color, &e),
+            None => match eval_with_context_mut(inp.trim(), context) {
+                Ok(out) => {
+                    let (figure, shown) = output
+                        .lock()
+                        .unwrap()
+                        .take_figure(&out, options.color && stdout.is_terminal());
+                    let mut printed = String::new();
+                    if let Some(figure) = figure {
+                        queue!(stdout, Print(&figure)).unwrap();
+                        printed.push_str(&session::without_colors(&figure));
+                    }
+                    if shown {
+                        if options.color {
+                            queue!(
+                                stdout,
+                                PrintStyledContent("····→ ".with(blue)),
+                                PrintStyledContent(out.to_string().with(orange)),
+                                Print("\n")
+                            )
+                            .unwrap();
+                        } else {
+                            queue!(stdout, Print("····→ "), Print(&out), Print("\n")).unwrap();
+                        }
+                        printed.push_str(&out.to_string());
                     }
+                    transcript.push(Entry {
+                        input: inp.trim().to_string(),
+                        output: printed.trim_end().to_string(),
+                    });
                 }
+                Err(e) => {
+                    output.lock().unwrap().forget_figure();
+                    print_repl_error(&mut stdout, options.color, &e.to_string());
+                    transcript.push(Entry {
+                        input: inp.trim().to_string(),
+                        output: format!("ERROR: {}", e),
+                    });
+                }
+            },
+        };
+        stdout.flush().unwrap();
+    }
 
-                table.printstd();
+    if let Some(path) = options.session.as_ref().filter(|_| autosave) {
+        save_session(path, context, &transcript);
+    }
+}
 
-                Ok(Value::Empty)
-            } else {
-                Err(EvalexprError::CustomMessage(table_help_message(
-                    &name,
-                    always_defensive,
-                    should_be_defensive,
-                )))
+/// Saves the variables and the transcript, telling which variables
+/// couldn't be saved. Returns whether the file was written.
+fn save_session(path: &str, context: &HashMapContext, transcript: &[Entry]) -> bool {
+    match session::save(path, context, transcript) {
+        Ok(skipped) => {
+            println!(
+                "Saved {} variable(s) to {}.",
+                context.iter_variable_names().count() - skipped.len(),
+                path
+            );
+            if !skipped.is_empty() {
+                eprintln!(
+                    "WARNING: could not save {}, their values can't be written back",
+                    skipped.join(", ")
+                );
             }
+            true
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            false
         }
-    })
+    }
 }
 
-fn main() {
-    let blue = style::Color::Rgb {
-        r: 115,
-        g: 170,
-        b: 218,
-    };
-    let orange = style::Color::Rgb {
-        r: 255,
-        g: 188,
-        b: 65,
+/// Runs a script file, or standard input when `path` is `-`.
+fn run_script_file(
+    path: &str,
+    context: &mut HashMapContext,
+    output: &SharedTableOutput,
+) -> ExitCode {
+    let result = if path == "-" {
+        run_script(
+            stdin().lock(),
+            "<stdin>",
+            context,
+            output,
+            &mut stdout(),
+            &mut stderr(),
+        )
+    } else {
+        match File::open(path) {
+            Ok(file) => run_script(
+                BufReader::new(file),
+                path,
+                context,
+                output,
+                &mut stdout(),
+                &mut stderr(),
+            ),
+            Err(e) => {
+                eprintln!("ERROR: {}: could not read file: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
     };
 
-    let mut inp: String = String::new();
-    let mut context = HashMapContext::new();
-    let mut magics: Vec<Magic> = match process_file_to_magic("init.rpg") {
-        Ok(r) => r,
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
         Err(e) => {
             eprintln!("ERROR: {}", e);
-            std::io::stdin().read_line(&mut inp).unwrap();
-            return;
+            ExitCode::FAILURE
         }
-    };
-
-    let mut stdout = stdout();
+    }
+}
 
-    for m in magics {
-        let mag = m;
-        let new_name = mag.name.clone();
-        if !mag.always_def {
-            context
-                .set_function(mag.name.clone(), generate_default_function(false, &mag))
-                .expect("Function should not have any problems loading!");
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("rpgcalc {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
 
-            context
-                .set_function(
-                    "def_".to_string() + &new_name,
-                    generate_default_function(true, &mag),
-                )
-                .expect("Function should not have any problems loading!");
+    if !options.color {
+        style::force_color_output(false);
+    }
 
-            context
-                .set_function(
-                    "t_".to_string() + &new_name,
-                    generate_default_table_function(false, &mag),
-                )
-                .expect("Function should not have any problems loading!");
+    let Some((magics, rules)) = load_checked_magics(&options) else {
+        return ExitCode::FAILURE;
+    };
 
-            context
-                .set_function(
-                    "t_def_".to_string() + &new_name,
-                    generate_default_table_function(true, &mag),
-                )
-                .expect("Function should not have any problems loading!");
-        } else {
-            context
-                .set_function(
-                    mag.name.clone(),
-                    generate_default_table_function(true, &mag),
-                )
-                .expect("Function should not have any problems loading!");
-            context
-                .set_function(
-                    "at_".to_string() + &new_name,
-                    generate_default_table_function(false, &mag),
-                )
-                .expect("Function should not have any problems loading!");
+    let output = TableOutput::shared();
+    output.lock().unwrap().format = options.table_format;
+    let mut context = HashMapContext::new();
+    register_magics(&mut context, &magics, &Arc::new(rules), &output)
+        .expect("Function should not have any problems loading!");
+
+    if !options.eval.is_empty() {
+        let mut status = ExitCode::SUCCESS;
+        for expr in &options.eval {
+            match eval_with_context_mut(expr, &mut context) {
+                Ok(out) => {
+                    let color = options.color && stdout().is_terminal();
+                    if let Err(e) = output
+                        .lock()
+                        .unwrap()
+                        .write_result(&out, &mut stdout(), color)
+                    {
+                        eprintln!("ERROR: {}", e);
+                        status = ExitCode::FAILURE;
+                    }
+                }
+                Err(e) => {
+                    output.lock().unwrap().forget_figure();
+                    eprintln!("ERROR: {}", e);
+                    status = ExitCode::FAILURE;
+                }
+            }
+        }
+        return status;
+    }
 
-            context
-                .set_function(
-                    "t_".to_string() + &new_name,
-                    generate_default_table_function(true, &mag),
-                )
-                .expect("Function should not have any problems loading!");
+    if let Some(path) = &options.script {
+        return run_script_file(path, &mut context, &output);
+    }
 
-            context
-                .set_function(
-                    "t_at_".to_string() + &new_name,
-                    generate_default_table_function(false, &mag),
-                )
-                .expect("Function should not have any problems loading!");
-        }
+    if !std::io::stdin().is_terminal() {
+        return run_script_file("-", &mut context, &output);
     }
 
-    loop {
-        queue!(
-            stdout,
-            PrintStyledContent(">>> ".with(style::Color::Rgb {
-                r: 137,
-                g: 221,
-                b: 255
-            }))
-        )
-        .unwrap();
-        execute!(stdout, crossterm::style::SetForegroundColor(orange)).unwrap();
-        std::io::stdin().read_line(&mut inp).unwrap();
-        let result = eval_with_context_mut(inp.trim(), &mut context);
-        match result {
-            Ok(out) => {
-                if !out.is_empty() {
-                    queue!(
-                        stdout,
-                        PrintStyledContent("····→ ".with(blue)),
-                        PrintStyledContent(out.to_string().with(orange)),
-                        Print("\n")
-                    )
-                    .unwrap();
-                }
-            }
-            Err(out) => {
-                queue!(
-                    stdout,
-                    PrintStyledContent("····→ : ".red()),
-                    PrintStyledContent(out.to_string().red()),
-                    Print("\n")
-                )
-                .unwrap();
-            }
-        };
-        inp.clear();
+    if !options.quiet {
+        println!(
+            "Loaded {} magic(s) from {}. Type :help for help.",
+            magics.len(),
+            options.init_files.join(", ")
+        );
     }
+
+    repl(&mut context, magics, &output, &options);
+    ExitCode::SUCCESS
 }
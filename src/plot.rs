@@ -0,0 +1,245 @@
+//! Line charts drawn with text, for plotting damage against mana in the
+//! terminal.
+
+use crossterm::style::{Color, Stylize};
+
+/// The marker and color of each series, in order. Markers differ too, so
+/// that curves can be told apart without colors.
+const STYLES: [(char, Color); 6] = [
+    ('•', Color::Green),
+    ('×', Color::Cyan),
+    ('+', Color::Magenta),
+    ('o', Color::Yellow),
+    ('*', Color::Blue),
+    ('#', Color::Red),
+];
+
+/// One curve of a chart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// `(x, y)` points sorted by `x`.
+    pub points: Vec<(i64, i64)>,
+}
+
+/// A chart of one or more series sharing both axes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chart {
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+}
+
+impl Chart {
+    pub fn new(x_label: &str, y_label: &str) -> Self {
+        Self {
+            x_label: x_label.to_string(),
+            y_label: y_label.to_string(),
+            series: Vec::new(),
+        }
+    }
+
+    pub fn add_series(&mut self, name: &str, points: Vec<(i64, i64)>) {
+        self.series.push(Series {
+            name: name.to_string(),
+            points,
+        });
+    }
+
+    /// The y value at `x` of a curve through `points`, interpolated
+    /// between them.
+    fn y_at(points: &[(i64, i64)], x: f64) -> Option<f64> {
+        let after = points.iter().position(|&(px, _)| px as f64 >= x)?;
+        let (x1, y1) = points[after];
+        if after == 0 || x1 as f64 == x {
+            return Some(y1 as f64);
+        }
+        let (x0, y0) = points[after - 1];
+        let t = (x - x0 as f64) / (x1 as f64 - x0 as f64);
+        Some(y0 as f64 + t * (y1 as f64 - y0 as f64))
+    }
+
+    /// Draws the chart in about `width` columns, with `height` rows for the
+    /// plot itself plus the axes, their labels and a legend. Points at
+    /// `i64::MIN` or `i64::MAX`, which a saturated cast from an infinite
+    /// result gives, are left out so that they don't squash the rest.
+    pub fn render(&self, width: usize, height: usize, color: bool) -> String {
+        let curves: Vec<Vec<(i64, i64)>> = self
+            .series
+            .iter()
+            .map(|series| {
+                series
+                    .points
+                    .iter()
+                    .copied()
+                    .filter(|&(_, y)| y != i64::MIN && y != i64::MAX)
+                    .collect()
+            })
+            .collect();
+        let points = curves.iter().flatten();
+        let (Some(x_min), Some(x_max)) = (
+            points.clone().map(|&(x, _)| x).min(),
+            points.clone().map(|&(x, _)| x).max(),
+        ) else {
+            return "nothing to plot\n".to_string();
+        };
+        let y_min = points.clone().map(|&(_, y)| y).min().unwrap().min(0);
+        let y_max = points
+            .map(|&(_, y)| y)
+            .max()
+            .unwrap()
+            .max(y_min.saturating_add(1));
+        // Spans and midpoints in f64 and i128, as the differences of far
+        // apart i64 values overflow.
+        let x_span = x_max as f64 - x_min as f64;
+        let y_span = y_max as f64 - y_min as f64;
+
+        let height = height.max(3);
+        let y_labels = [
+            y_max.to_string(),
+            ((y_min as i128 + y_max as i128) / 2).to_string(),
+            y_min.to_string(),
+        ];
+        let label_width = y_labels.iter().map(|l| l.len()).max().unwrap_or(1);
+        let plot_width = width.saturating_sub(label_width + 2).max(10);
+
+        // Column by column, top row first; each cell holds the series drawn
+        // in it.
+        let mut grid: Vec<Vec<Option<usize>>> = vec![vec![None; height]; plot_width];
+        for (index, points) in curves.iter().enumerate() {
+            for (column, cells) in grid.iter_mut().enumerate() {
+                let x = x_min as f64 + x_span * column as f64 / (plot_width - 1) as f64;
+                let Some(y) = Self::y_at(points, x) else {
+                    continue;
+                };
+                let row = ((y - y_min as f64) / y_span * (height - 1) as f64).round() as usize;
+                cells[height - 1 - row.min(height - 1)] = Some(index);
+            }
+        }
+
+        let marker = |index: usize| {
+            let (marker, marker_color) = STYLES[index % STYLES.len()];
+            if color {
+                marker.with(marker_color).to_string()
+            } else {
+                marker.to_string()
+            }
+        };
+
+        let mut out = format!("{:>w$}\n", self.y_label, w = label_width);
+        for r in 0..height {
+            let label = match r {
+                0 => &y_labels[0],
+                r if r == height / 2 => &y_labels[1],
+                r if r == height - 1 => &y_labels[2],
+                _ => "",
+            };
+            let axis = if label.is_empty() { '│' } else { '┤' };
+            out.push_str(&format!("{:>w$} {}", label, axis, w = label_width));
+            for cells in &grid {
+                match cells[r] {
+                    Some(index) => out.push_str(&marker(index)),
+                    None => out.push(' '),
+                }
+            }
+            out.truncate(out.trim_end_matches(' ').len());
+            out.push('\n');
+        }
+
+        out.push_str(&format!(
+            "{:>w$} └{}\n",
+            "",
+            "─".repeat(plot_width),
+            w = label_width
+        ));
+        let (first, middle, last) = (
+            x_min.to_string(),
+            ((x_min as i128 + x_max as i128) / 2).to_string(),
+            x_max.to_string(),
+        );
+        let mut x_axis = vec![' '; plot_width];
+        for (text, at) in [
+            (&first, 0),
+            (&middle, plot_width.saturating_sub(middle.len()) / 2),
+            (&last, plot_width.saturating_sub(last.len())),
+        ] {
+            for (i, c) in text.chars().enumerate() {
+                if let Some(cell) = x_axis.get_mut(at + i) {
+                    *cell = c;
+                }
+            }
+        }
+        out.push_str(&format!(
+            "{:>w$}  {}\n",
+            "",
+            x_axis.iter().collect::<String>().trim_end(),
+            w = label_width
+        ));
+        out.push_str(&format!(
+            "{:>w$}  {:^pw$}\n",
+            "",
+            self.x_label,
+            w = label_width,
+            pw = plot_width
+        ));
+        let out = out.trim_end().to_string() + "\n";
+
+        let legend: Vec<String> = self
+            .series
+            .iter()
+            .enumerate()
+            .map(|(index, series)| format!("{} {}", marker(index), series.name))
+            .collect();
+        out + &legend.join("   ") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_axes_labels_and_legend() {
+        let mut chart = Chart::new("mana", "damage");
+        chart.add_series("fire", vec![(0, 0), (10, 10)]);
+        assert_eq!(
+            chart.render(16, 3, false),
+            "damage\n\
+             10 ┤         •••\n\
+             \x205 ┤   ••••••\n\
+             \x200 ┤•••\n\
+             \x20  └────────────\n\
+             \x20   0    5    10\n\
+             \x20       mana\n\
+             • fire\n"
+        );
+    }
+
+    #[test]
+    fn overlays_series_with_their_own_markers() {
+        let mut chart = Chart::new("mana", "damage");
+        chart.add_series("fire", vec![(1, 2), (2, 4)]);
+        chart.add_series("def_fire", vec![(1, 3), (2, 6)]);
+        let text = chart.render(40, 8, false);
+        assert!(text.contains('•') && text.contains('×'));
+        assert!(text.ends_with("• fire   × def_fire\n"));
+        assert_eq!(
+            Chart::new("mana", "damage").render(40, 8, false),
+            "nothing to plot\n"
+        );
+    }
+
+    #[test]
+    fn far_apart_and_saturated_values() {
+        let mut chart = Chart::new("mana", "life");
+        chart.add_series("ice", vec![(0, i64::MAX), (5, 3), (10, 9)]);
+        chart.add_series("def_ice", vec![(0, -30), (10, i64::MAX - 1)]);
+        let text = chart.render(40, 8, false);
+        assert!(text.contains("9223372036854775806 ┤"));
+        assert!(text.contains("-30 ┤"));
+
+        let mut chart = Chart::new("mana", "life");
+        chart.add_series("ice", vec![(0, i64::MIN), (5, 3), (10, 9)]);
+        assert!(chart.render(40, 8, false).starts_with("life\n9 ┤"));
+    }
+}
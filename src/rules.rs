@@ -0,0 +1,284 @@
+//! The numbers the formulas are built on, so they can be rebalanced from a
+//! `rules` block instead of a recompile.
+
+use std::fmt;
+
+use evalexpr::{
+    build_operator_tree, ContextWithMutableVariables, EvalexprError, EvalexprResult,
+    HashMapContext, Node, Value,
+};
+
+use crate::magic::{MagicRank, MagicType};
+
+/// The variables an accuracy formula can read: the accuracy points, as an
+/// Int.
+pub const ACCURACY_VARIABLES: &[&str] = &["accuracy"];
+
+/// The variables a damage formula can read. `mana` is an Int, `accuracy` is
+/// the factor from the accuracy curve and the multipliers are Floats.
+pub const DAMAGE_VARIABLES: &[&str] = &["mana", "accuracy", "rank_mult", "race_mult"];
+
+/// The variables a defense formula can read: those of a damage formula plus
+/// the effective `damage`.
+pub const DEFENSE_VARIABLES: &[&str] = &["mana", "accuracy", "rank_mult", "race_mult", "damage"];
+
+/// Why a formula from a rules file was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormulaError {
+    /// evalexpr could not parse the formula.
+    Syntax(String),
+    /// The formula reads a variable that isn't bound for it.
+    UnknownVariable {
+        name: String,
+        allowed: &'static [&'static str],
+    },
+    /// The formula parsed but failed on sample values, e.g. because it
+    /// calls an unknown function or doesn't produce a number.
+    Eval(String),
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormulaError::Syntax(e) => write!(f, "{}", e),
+            FormulaError::UnknownVariable { name, allowed } => write!(
+                f,
+                "unknown variable `{}`, use one of {}",
+                name,
+                allowed.join(", ")
+            ),
+            FormulaError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+/// A formula written in a rules file, compiled once when it is loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formula {
+    source: String,
+    tree: Node,
+}
+
+impl Formula {
+    /// Compiles `source`, which may only read `variables`. The formula is
+    /// also evaluated once with sample values, so that type errors and
+    /// unknown functions are caught here rather than mid-session.
+    pub fn new(source: &str, variables: &'static [&'static str]) -> Result<Self, FormulaError> {
+        let tree = build_operator_tree(source).map_err(|e| FormulaError::Syntax(e.to_string()))?;
+        if let Some(name) = tree
+            .iter_variable_identifiers()
+            .find(|name| !variables.contains(name))
+        {
+            return Err(FormulaError::UnknownVariable {
+                name: name.to_string(),
+                allowed: variables,
+            });
+        }
+
+        let formula = Self {
+            source: source.to_string(),
+            tree,
+        };
+        let samples: Vec<(&str, Value)> = variables
+            .iter()
+            .map(|&name| match name {
+                "mana" => (name, Value::Int(10)),
+                "accuracy" if variables == ACCURACY_VARIABLES => (name, Value::Int(10)),
+                _ => (name, Value::Float(1.0)),
+            })
+            .collect();
+        formula
+            .eval(&samples)
+            .map_err(|e| FormulaError::Eval(e.to_string()))?;
+        Ok(formula)
+    }
+
+    /// The formula as written in the rules file.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn try_eval(&self, variables: &[(&str, Value)]) -> EvalexprResult<f64> {
+        let mut context = HashMapContext::new();
+        for (name, value) in variables {
+            context.set_value(name.to_string(), value.clone())?;
+        }
+        self.tree.eval_number_with_context(&context)
+    }
+
+    /// Evaluates the formula. Formulas are checked when they are compiled,
+    /// but may still fail on other values than the samples, e.g. by taking
+    /// the logarithm of 0. A result that isn't a finite number is an error
+    /// too.
+    pub fn eval(&self, variables: &[(&str, Value)]) -> EvalexprResult<f64> {
+        let result = self.try_eval(variables)?;
+        if result.is_finite() {
+            return Ok(result);
+        }
+        let values: Vec<String> = variables
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        Err(EvalexprError::CustomMessage(format!(
+            "the formula `{}` gives {} for {}",
+            self.source,
+            result,
+            values.join(", ")
+        )))
+    }
+}
+
+/// How accuracy points turn into the factor applied to raw damage.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccuracyCurve {
+    /// `base + points * slope`.
+    Linear { base: f64, slope: f64 },
+    /// An S-curve from `min` to `max`, halfway at `midpoint` points.
+    Logistic {
+        min: f64,
+        max: f64,
+        midpoint: f64,
+        steepness: f64,
+    },
+    /// `(from, factor)` pairs sorted by `from`. Each factor applies from its
+    /// number of points up to the next pair; below the first pair the first
+    /// factor applies.
+    Steps(Vec<(i64, f64)>),
+    /// One curve for even accuracy points and another for odd ones.
+    Parity {
+        even: Box<AccuracyCurve>,
+        odd: Box<AccuracyCurve>,
+    },
+    /// Another curve, never going above `max`.
+    Capped { max: f64, curve: Box<AccuracyCurve> },
+    /// A formula of the accuracy points, see [`ACCURACY_VARIABLES`].
+    Expression(Formula),
+}
+
+impl Default for AccuracyCurve {
+    fn default() -> Self {
+        AccuracyCurve::Linear {
+            base: 0.5,
+            slope: 0.025,
+        }
+    }
+}
+
+/// How the effective damage of a defensive cast turns into barrier life.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefenseFormula {
+    /// `factor * damage`.
+    Scaled { factor: f64 },
+    /// `factor * damage - ln(damage)`, or 0 when the damage is 0 or less.
+    ScaledLog { factor: f64 },
+    /// A formula, see [`DEFENSE_VARIABLES`].
+    Expression(Formula),
+}
+
+impl Default for DefenseFormula {
+    fn default() -> Self {
+        DefenseFormula::Scaled { factor: 1.3 }
+    }
+}
+
+/// How a cast's mana, rank multiplier, accuracy and race multiplier combine
+/// into its effective damage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DamageFormula {
+    /// `floor(mana * floor(rank_mult) * accuracy * race_mult)`.
+    #[default]
+    Standard,
+    /// A formula, see [`DAMAGE_VARIABLES`].
+    Expression(Formula),
+}
+
+/// Everything the formulas need to know about one origin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    pub name: MagicType,
+    /// Damage per mana, indexed by rank.
+    pub multipliers: [f64; 6],
+    pub accuracy: AccuracyCurve,
+    pub damage: DamageFormula,
+    pub defense: DefenseFormula,
+}
+
+impl Origin {
+    /// An origin with the same numbers as the built-in ORDER.
+    pub fn new(name: MagicType) -> Self {
+        Self {
+            name,
+            multipliers: [4.0, 6.0, 9.0, 13.0, 18.0, 24.0],
+            accuracy: AccuracyCurve::default(),
+            damage: DamageFormula::default(),
+            defense: DefenseFormula::default(),
+        }
+    }
+
+    fn chaos() -> Self {
+        Self {
+            name: MagicType::CHAOS,
+            multipliers: [5.5, 7.5, 10.5, 14.5, 19.5, 25.5],
+            accuracy: AccuracyCurve::Parity {
+                even: Box::default(),
+                odd: Box::new(AccuracyCurve::Linear {
+                    base: 0.5,
+                    slope: 0.18 / 8.0,
+                }),
+            },
+            damage: DamageFormula::default(),
+            defense: DefenseFormula::ScaledLog { factor: 1.3 },
+        }
+    }
+
+    /// Damage per point of mana at `rank`.
+    pub fn multiplier(&self, rank: MagicRank) -> f64 {
+        self.multipliers[rank.index()]
+    }
+}
+
+/// Every number the formulas read. [`Ruleset::default`] is the built-in
+/// ruleset with the ORDER and CHAOS origins; `rules` blocks in init files
+/// override parts of it and declare new origins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ruleset {
+    origins: Vec<Origin>,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self {
+            origins: vec![Origin::new(MagicType::ORDER), Origin::chaos()],
+        }
+    }
+}
+
+impl Ruleset {
+    /// The origin called `typ`, if it was declared.
+    pub fn origin(&self, typ: &MagicType) -> Option<&Origin> {
+        self.origins.iter().find(|o| o.name == *typ)
+    }
+
+    pub fn origin_mut(&mut self, typ: &MagicType) -> Option<&mut Origin> {
+        self.origins.iter_mut().find(|o| o.name == *typ)
+    }
+
+    /// The origin called `typ`, declaring it with ORDER's numbers first if
+    /// it doesn't exist yet.
+    pub fn declare_origin(&mut self, typ: &MagicType) -> &mut Origin {
+        match self.origins.iter().position(|o| o.name == *typ) {
+            Some(index) => &mut self.origins[index],
+            None => {
+                self.origins.push(Origin::new(typ.clone()));
+                self.origins.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Every declared origin, built-in ones first.
+    pub fn origins(&self) -> &[Origin] {
+        &self.origins
+    }
+}
@@ -0,0 +1,135 @@
+use evalexpr::{eval_with_context_mut, HashMapContext};
+use rpgcalc::table::SharedTableOutput;
+use std::io::{self, BufRead, Write};
+
+/// Evaluates every line of `input` against `context`, the same way the REPL
+/// does, but without prompts or styling. Blank lines and lines starting with
+/// `#` are skipped. Results go to `out` and errors to `err`, prefixed with
+/// `source` and the line number. A line giving a table or a chart is
+/// written as one, tables in the format of `output`.
+///
+/// Returns how many lines failed.
+pub fn run_script<R: BufRead, O: Write, E: Write>(
+    input: R,
+    source: &str,
+    context: &mut HashMapContext,
+    output: &SharedTableOutput,
+    out: &mut O,
+    err: &mut E,
+) -> io::Result<usize> {
+    let mut failures = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let expr = line.trim();
+        if expr.is_empty() || expr.starts_with('#') {
+            continue;
+        }
+
+        match eval_with_context_mut(expr, context) {
+            Ok(value) => output.lock().unwrap().write_result(&value, out, false)?,
+            Err(e) => {
+                output.lock().unwrap().forget_figure();
+                failures += 1;
+                writeln!(err, "{}:{}: ERROR: {}", source, index + 1, e)?;
+            }
+        }
+    }
+
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::{ContextWithMutableFunctions, Function, Value};
+    use rpgcalc::functions::register_magics;
+    use rpgcalc::table::{Table, TableFormat, TableOutput};
+    use rpgcalc::Ruleset;
+    use std::sync::Arc;
+
+    fn squares() -> Table {
+        let mut table = Table::new(&["n", "square"]);
+        for n in 1..=3 {
+            table.add_row(&[n, n * n]);
+        }
+        table
+    }
+
+    fn run(script: &str) -> (usize, String, String) {
+        let output = TableOutput::shared();
+        output.lock().unwrap().format = TableFormat::Csv;
+        let mut context = HashMapContext::new();
+        register_magics(&mut context, &[], &Arc::new(Ruleset::default()), &output).unwrap();
+        let table_output = output.clone();
+        context
+            .set_function(
+                "squares".to_string(),
+                Function::new(move |_| Ok(table_output.lock().unwrap().keep(squares()))),
+            )
+            .unwrap();
+        // Like `best_`, a value printed after the table it was picked from.
+        let table_output = output.clone();
+        context
+            .set_function(
+                "pick".to_string(),
+                Function::new(move |_| {
+                    let picked = Value::Tuple(vec![Value::Int(3), Value::Int(9)]);
+                    Ok(table_output.lock().unwrap().keep_for(squares(), picked))
+                }),
+            )
+            .unwrap();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let failures = run_script(
+            script.as_bytes(),
+            "prep.calc",
+            &mut context,
+            &output,
+            &mut out,
+            &mut err,
+        )
+        .unwrap();
+        (
+            failures,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn lines_share_one_context() {
+        assert_eq!(
+            run("# prep sheet\na = 12\n\na * 2\n"),
+            (0, "24\n".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        let (failures, out, err) = run("1 + 1\nnope(3)\n2 + 2\n");
+        assert_eq!(failures, 1);
+        assert_eq!(out, "2\n4\n");
+        assert!(err.starts_with("prep.calc:2: ERROR: "));
+    }
+
+    #[test]
+    fn tables_print_only_at_the_top() {
+        let (failures, out, _) = run("squares()\nmax(squares())\nmin(squares(), 2)\n");
+        assert_eq!(failures, 0);
+        assert_eq!(out, "n,square\n1,1\n2,4\n3,9\n9\n1\n");
+
+        // A table is only printed for the line that made it.
+        let (failures, out, _) = run("t = squares()\nlen(t)\nt\n");
+        assert_eq!(failures, 0);
+        assert_eq!(out, "3\n((1, 1), (2, 4), (3, 9))\n");
+
+        let (failures, out, _) = run("pick()\n1 + 1\n(3, 9)\n");
+        assert_eq!(failures, 0);
+        assert_eq!(out, "n,square\n1,1\n2,4\n3,9\n(3, 9)\n2\n(3, 9)\n");
+
+        let (failures, out, _) = run("pick() + nope()\n(3, 9)\n");
+        assert_eq!(failures, 1);
+        assert_eq!(out, "(3, 9)\n");
+    }
+}
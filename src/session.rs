@@ -0,0 +1,324 @@
+use evalexpr::{
+    build_operator_tree, HashMapContext, IterateVariablesContext, Node, Operator, Value,
+};
+use std::fmt::Write as _;
+use std::fs;
+
+/// The first line of a session file.
+const HEADER: &str = "# rpgcalc session";
+
+/// Marks where the variables end and the transcript begins.
+const TRANSCRIPT: &str = "# Transcript";
+
+/// Starts an expression in the transcript; the lines after it, up to the
+/// next one, are its output.
+const INPUT: &str = "# >>> ";
+
+/// An expression evaluated in the REPL and what it printed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    pub output: String,
+}
+
+/// `value` written on one line so that evaluating it gives it back, or
+/// `None` for values evalexpr can't write, such as a tuple of one. Line
+/// breaks in strings are written as `\n` and `\r`, which evalexpr doesn't
+/// know, so [`restore`] turns them back before evaluating the line.
+pub fn value_source(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(format!(
+            "\"{}\"",
+            s.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        )),
+        // Debug keeps the `.0` of whole floats, so they stay floats.
+        Value::Float(f) if f.is_finite() => Some(format!("{:?}", f)),
+        Value::Float(_) => None,
+        Value::Int(i) => Some(i.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Tuple(values) if values.len() >= 2 => {
+            let values = values
+                .iter()
+                .map(value_source)
+                .collect::<Option<Vec<String>>>()?;
+            Some(format!("({})", values.join(", ")))
+        }
+        Value::Tuple(_) => None,
+        Value::Empty => Some("()".to_string()),
+    }
+}
+
+/// `text` without the escape sequences that color it, to keep what was
+/// printed in the transcript.
+pub fn without_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+        } else if chars.next() == Some('[') {
+            // A control sequence runs up to its final byte, e.g. the `m` of
+            // `\x1b[32m`.
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    plain
+}
+
+/// The variables of `context`, as assignments sorted by name, followed by
+/// the transcript as comments. Variables that can't be written are listed
+/// in `skipped`.
+pub fn render(context: &HashMapContext, transcript: &[Entry], skipped: &mut Vec<String>) -> String {
+    let mut variables: Vec<(String, Value)> = context.iter_variables().collect();
+    variables.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = format!("{}\n", HEADER);
+    for (name, value) in variables {
+        match value_source(&value) {
+            Some(source) => writeln!(out, "{} = {}", name, source).unwrap(),
+            None => skipped.push(name),
+        }
+    }
+
+    writeln!(out, "\n{}", TRANSCRIPT).unwrap();
+    for entry in transcript {
+        writeln!(out, "{}{}", INPUT, entry.input).unwrap();
+        for line in entry.output.lines() {
+            writeln!(out, "# {}", line).unwrap();
+        }
+    }
+    out
+}
+
+/// Saves the variables of `context` and the transcript to `path`. Returns
+/// the names of the variables that couldn't be saved.
+pub fn save(
+    path: &str,
+    context: &HashMapContext,
+    transcript: &[Entry],
+) -> Result<Vec<String>, String> {
+    let mut skipped = Vec::new();
+    fs::write(path, render(context, transcript, &mut skipped))
+        .map_err(|e| format!("could not write `{}`: {}", path, e))?;
+    Ok(skipped)
+}
+
+/// `source` with the `\n` and `\r` escapes of its strings turned back into
+/// line breaks, which evalexpr reads as they are.
+fn with_line_breaks(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut in_string = false;
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => match chars.next() {
+                Some('n') => {
+                    out.push('\n');
+                    continue;
+                }
+                Some('r') => {
+                    out.push('\r');
+                    continue;
+                }
+                Some(escaped) => {
+                    out.push(c);
+                    out.push(escaped);
+                    continue;
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Whether `tree` assigns a value written out in full to one variable, like
+/// `a = (1, "x")`, without calling functions or reading variables.
+fn is_literal_assignment(tree: &Node) -> bool {
+    matches!(tree.children(), [assign] if *assign.operator() == Operator::Assign)
+        && tree.iter_function_identifiers().next().is_none()
+        && tree.iter_read_variable_identifiers().next().is_none()
+}
+
+/// Replaces the variables of `context` with those of a session file and
+/// gives its transcript. Every line before the transcript must be a
+/// comment or a `<name> = <value>` assignment, so that loading a file never
+/// runs a function. Nothing changes if any line fails.
+pub fn restore(
+    text: &str,
+    source: &str,
+    context: &mut HashMapContext,
+) -> Result<Vec<Entry>, String> {
+    let mut fresh = context.clone();
+    fresh.clear_variables();
+    let mut transcript: Vec<Entry> = Vec::new();
+    let mut in_transcript = false;
+
+    for (index, line) in text.lines().enumerate() {
+        if line == TRANSCRIPT {
+            in_transcript = true;
+        } else if in_transcript {
+            if let Some(input) = line.strip_prefix(INPUT) {
+                transcript.push(Entry {
+                    input: input.to_string(),
+                    output: String::new(),
+                });
+            } else if let Some(entry) = transcript.last_mut() {
+                let output = line
+                    .strip_prefix("# ")
+                    .unwrap_or(line.trim_start_matches('#'));
+                if !entry.output.is_empty() {
+                    entry.output.push('\n');
+                }
+                entry.output.push_str(output);
+            }
+        } else {
+            let expr = line.trim();
+            if expr.is_empty() || expr.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("{}:{}: {}", source, index + 1, e);
+            let tree =
+                build_operator_tree(&with_line_breaks(expr)).map_err(|e| error(e.to_string()))?;
+            if !is_literal_assignment(&tree) {
+                return Err(error(format!(
+                    "expected `<name> = <value>`, found `{}`",
+                    expr
+                )));
+            }
+            tree.eval_with_context_mut(&mut fresh)
+                .map_err(|e| error(e.to_string()))?;
+        }
+    }
+
+    *context = fresh;
+    Ok(transcript)
+}
+
+/// Reads a session file into `context`, see [`restore`].
+pub fn load(path: &str, context: &mut HashMapContext) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read `{}`: {}", path, e))?;
+    restore(&text, path, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::{eval_with_context_mut, Context, ContextWithMutableVariables};
+
+    #[test]
+    fn values_round_trip() {
+        for value in [
+            Value::Int(-12),
+            Value::Float(1.0),
+            Value::Float(1e-7),
+            Value::String("say \"hi\" \\o/".to_string()),
+            Value::Boolean(true),
+            Value::Tuple(vec![
+                Value::Tuple(vec![Value::Int(1), Value::Float(2.5)]),
+                Value::String("fire".to_string()),
+            ]),
+        ] {
+            let source = value_source(&value).unwrap();
+            assert_eq!(evalexpr::eval(&source), Ok(value), "{}", source);
+        }
+        assert_eq!(value_source(&Value::Tuple(vec![Value::Int(1)])), None);
+        assert_eq!(value_source(&Value::Float(f64::NAN)), None);
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let mut context = HashMapContext::new();
+        eval_with_context_mut("b = 2.0; a = (1, \"x\")", &mut context).unwrap();
+        let transcript = vec![
+            Entry {
+                input: "b * 2".to_string(),
+                output: "4".to_string(),
+            },
+            Entry {
+                input: "t_fire(1, 2)".to_string(),
+                output: "Mana,Damage\n1,3\n2,7".to_string(),
+            },
+        ];
+
+        let text = render(&context, &transcript, &mut Vec::new());
+        assert_eq!(
+            text,
+            "# rpgcalc session\na = (1, \"x\")\nb = 2.0\n\n# Transcript\n\
+             # >>> b * 2\n# 4\n# >>> t_fire(1, 2)\n# Mana,Damage\n# 1,3\n# 2,7\n"
+        );
+
+        let mut restored = HashMapContext::new();
+        eval_with_context_mut("c = 3", &mut restored).unwrap();
+        assert_eq!(restore(&text, "s", &mut restored), Ok(transcript));
+        let mut names: Vec<String> = restored.iter_variable_names().collect();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn multi_line_strings_round_trip() {
+        let chart = "damage\n  12 | *\r\n   0 +--\\n \"mana\"";
+        let mut context = HashMapContext::new();
+        context
+            .set_value("p".to_string(), Value::String(chart.to_string()))
+            .unwrap();
+
+        let text = render(&context, &[], &mut Vec::new());
+        assert!(text.starts_with(
+            "# rpgcalc session\np = \"damage\\n  12 | *\\r\\n   0 +--\\\\n \\\"mana\\\"\"\n"
+        ));
+
+        let mut restored = HashMapContext::new();
+        assert_eq!(restore(&text, "s", &mut restored), Ok(Vec::new()));
+        assert_eq!(
+            restored.get_value("p"),
+            Some(&Value::String(chart.to_string()))
+        );
+    }
+
+    #[test]
+    fn transcripts_leave_colors_out() {
+        assert_eq!(
+            without_colors("| \x1b[1m\x1b[38;5;10m12\x1b[39m\x1b[0m | 7 |"),
+            "| 12 | 7 |"
+        );
+        assert_eq!(without_colors("····→ (10, 30)"), "····→ (10, 30)");
+    }
+
+    #[test]
+    fn failed_loads_change_nothing() {
+        let mut context = HashMapContext::new();
+        eval_with_context_mut("c = 3", &mut context).unwrap();
+        let err = restore("a = 1\nb = (1, \"x\"\n", "s.calc", &mut context).unwrap_err();
+        assert!(err.starts_with("s.calc:2: "));
+        for line in [
+            "save_table(\"x.csv\")",
+            "a = t_fire(1, 1000000000)",
+            "a = max(1, 2)",
+            "a = c",
+            "a = 1; b = 2",
+            "a == 1",
+        ] {
+            assert_eq!(
+                restore(line, "s.calc", &mut context),
+                Err(format!(
+                    "s.calc:1: expected `<name> = <value>`, found `{}`",
+                    line
+                ))
+            );
+        }
+        let names: Vec<String> = context.iter_variable_names().collect();
+        assert_eq!(names, ["c"]);
+    }
+}
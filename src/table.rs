@@ -0,0 +1,686 @@
+//! Tables printed by the calculator functions, and the formats they can be
+//! printed and saved in.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, stdout, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crossterm::queue;
+use crossterm::style::{Attribute, Color, ResetColor, SetAttribute, SetForegroundColor};
+use evalexpr::Value;
+use prettytable::{color, Attr, Cell, Row};
+
+use crate::plot::Chart;
+
+/// How a table is written out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableFormat {
+    /// Boxed and colored, for the terminal.
+    Terminal,
+    Csv,
+    /// An array with one object per row, keyed by the header.
+    Json,
+    /// A GitHub Markdown table.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+}
+
+impl TableFormat {
+    pub const ALL: [TableFormat; 5] = [
+        TableFormat::Terminal,
+        TableFormat::Csv,
+        TableFormat::Json,
+        TableFormat::Markdown,
+        TableFormat::Html,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TableFormat::Terminal => "terminal",
+            TableFormat::Csv => "csv",
+            TableFormat::Json => "json",
+            TableFormat::Markdown => "markdown",
+            TableFormat::Html => "html",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// The format a file should be saved in, judging by its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match Path::new(path).extension()?.to_str()? {
+            "csv" => Some(TableFormat::Csv),
+            "json" => Some(TableFormat::Json),
+            "md" | "markdown" => Some(TableFormat::Markdown),
+            "html" | "htm" => Some(TableFormat::Html),
+            "txt" => Some(TableFormat::Terminal),
+            _ => None,
+        }
+    }
+}
+
+/// Why a cell stands out in the terminal and in HTML.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    /// The best value of its row.
+    Best,
+    /// A cast hit by the odd accuracy penalty.
+    Penalty,
+}
+
+/// A table of text cells under a header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// `(row, column, highlight)`, indexed into `rows`.
+    highlights: Vec<(usize, usize, Highlight)>,
+    /// Columns that [`Table::to_value`] leaves out.
+    left_out: Vec<usize>,
+}
+
+impl Table {
+    pub fn new<S: ToString>(header: &[S]) -> Self {
+        Self {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn add_row<S: ToString>(&mut self, row: &[S]) {
+        self.rows.push(row.iter().map(|c| c.to_string()).collect());
+    }
+
+    /// Leaves `column` out of the rows given by [`Table::to_value`], e.g.
+    /// because it repeats the same argument in every row.
+    pub fn leave_out_of_value(&mut self, column: usize) {
+        self.left_out.push(column);
+    }
+
+    /// Highlights a cell of the last row added.
+    pub fn highlight_last(&mut self, column: usize, highlight: Highlight) {
+        if let Some(row) = self.rows.len().checked_sub(1) {
+            self.highlights.push((row, column, highlight));
+        }
+    }
+
+    /// How the cell at `row` and `column` of `rows` stands out, if it does.
+    pub fn highlight(&self, row: usize, column: usize) -> Option<Highlight> {
+        self.highlights
+            .iter()
+            .find(|&&(r, c, _)| r == row && c == column)
+            .map(|&(_, _, h)| h)
+    }
+
+    fn to_prettytable(&self) -> prettytable::Table {
+        let mut table = prettytable::Table::new();
+        table.add_row(Row::new(self.header.iter().map(|h| Cell::new(h)).collect()));
+        for (r, row) in self.rows.iter().enumerate() {
+            let cells = row
+                .iter()
+                .enumerate()
+                .map(|(c, text)| {
+                    let mut cell = Cell::new(text);
+                    match self.highlight(r, c) {
+                        Some(Highlight::Best) => {
+                            cell.style(Attr::Bold);
+                            cell.style(Attr::ForegroundColor(color::GREEN));
+                        }
+                        Some(Highlight::Penalty) => {
+                            cell.style(Attr::ForegroundColor(color::RED));
+                        }
+                        None => {}
+                    }
+                    cell
+                })
+                .collect();
+            table.add_row(Row::new(cells));
+        }
+        table
+    }
+
+    /// Prints the table to standard output, with its highlights colored if
+    /// `color` is set and standard output is a terminal.
+    pub fn print(&self, format: TableFormat, color: bool) {
+        print!(
+            "{}",
+            self.render_colored(format, color && stdout().is_terminal())
+        );
+    }
+
+    /// The table as text in `format`, the terminal format colored if
+    /// `color` is set.
+    pub fn render_colored(&self, format: TableFormat, color: bool) -> String {
+        match format {
+            TableFormat::Terminal if color => {
+                let mut colored = Colored(Vec::new());
+                self.to_prettytable()
+                    .print_term(&mut colored)
+                    .expect("Writing to memory should not fail!");
+                String::from_utf8_lossy(&colored.0).into_owned()
+            }
+            _ => self.render(format),
+        }
+    }
+
+    /// The table as text in `format`. The terminal format has no colors.
+    pub fn render(&self, format: TableFormat) -> String {
+        match format {
+            TableFormat::Terminal => self.to_prettytable().to_string(),
+            TableFormat::Csv => self.to_csv(),
+            TableFormat::Json => self.to_json(),
+            TableFormat::Markdown => self.to_markdown(),
+            TableFormat::Html => self.to_html(),
+        }
+    }
+
+    /// The rows as a tuple of tuples, without the header and the columns
+    /// left out of the value. Cells that are numbers become Ints or Floats
+    /// and anything else a String.
+    pub fn to_value(&self) -> Value {
+        let cell = |text: &String| {
+            if let Ok(int) = text.parse::<i64>() {
+                Value::Int(int)
+            } else if let Ok(float) = text.parse::<f64>() {
+                Value::Float(float)
+            } else {
+                Value::String(text.clone())
+            }
+        };
+        Value::Tuple(
+            self.rows
+                .iter()
+                .map(|row| {
+                    Value::Tuple(
+                        row.iter()
+                            .enumerate()
+                            .filter(|(column, _)| !self.left_out.contains(column))
+                            .map(|(_, text)| cell(text))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &Vec<String>> {
+        std::iter::once(&self.header).chain(&self.rows)
+    }
+
+    fn to_csv(&self) -> String {
+        let mut out = String::new();
+        for line in self.lines() {
+            let cells: Vec<String> = line
+                .iter()
+                .map(|cell| {
+                    if cell.contains([',', '"', '\n']) {
+                        format!("\"{}\"", cell.replace('"', "\"\""))
+                    } else {
+                        cell.clone()
+                    }
+                })
+                .collect();
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn to_json(&self) -> String {
+        fn string(text: &str) -> String {
+            let mut out = String::from("\"");
+            for c in text.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let fields: Vec<String> = self
+                    .header
+                    .iter()
+                    .zip(row)
+                    .map(|(key, cell)| {
+                        let value = match cell.parse::<i64>() {
+                            Ok(number) => number.to_string(),
+                            Err(_) => string(cell),
+                        };
+                        format!("{}: {}", string(key), value)
+                    })
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+        format!("[\n{}\n]\n", rows.join(",\n"))
+    }
+
+    fn to_markdown(&self) -> String {
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut out = line(&self.header);
+        out.push_str(&format!("|{}\n", "---|".repeat(self.header.len())));
+        for row in &self.rows {
+            out.push_str(&line(row));
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        fn escape(text: &str) -> String {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        }
+
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n\
+             table { border-collapse: collapse; }\n\
+             th, td { border: 1px solid #888; padding: 2px 8px; text-align: right; }\n\
+             .best { font-weight: bold; color: #2a2; }\n\
+             .penalty { color: #c22; }\n\
+             </style>\n</head>\n<body>\n<table>\n<tr>",
+        );
+        for cell in &self.header {
+            write!(out, "<th>{}</th>", escape(cell)).unwrap();
+        }
+        out.push_str("</tr>\n");
+        for (r, row) in self.rows.iter().enumerate() {
+            out.push_str("<tr>");
+            for (c, cell) in row.iter().enumerate() {
+                match self.highlight(r, c) {
+                    Some(Highlight::Best) => out.push_str("<td class=\"best\">"),
+                    Some(Highlight::Penalty) => out.push_str("<td class=\"penalty\">"),
+                    None => out.push_str("<td>"),
+                }
+                write!(out, "{}</td>", escape(cell)).unwrap();
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+/// A terminal that keeps what prettytable prints to it, with its colors
+/// as escape sequences, so that colored tables can be written anywhere.
+struct Colored(Vec<u8>);
+
+impl Write for Colored {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl term::Terminal for Colored {
+    type Output = Vec<u8>;
+
+    fn fg(&mut self, color: color::Color) -> term::Result<()> {
+        let color = u8::try_from(color).map_err(|_| term::Error::ColorOutOfRange)?;
+        Ok(queue!(self.0, SetForegroundColor(Color::AnsiValue(color)))?)
+    }
+
+    fn bg(&mut self, _: color::Color) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn attr(&mut self, attr: Attr) -> term::Result<()> {
+        match attr {
+            Attr::Bold => Ok(queue!(self.0, SetAttribute(Attribute::Bold))?),
+            Attr::ForegroundColor(color) => self.fg(color),
+            _ => Err(term::Error::NotSupported),
+        }
+    }
+
+    fn supports_attr(&self, attr: Attr) -> bool {
+        matches!(attr, Attr::Bold | Attr::ForegroundColor(_))
+    }
+
+    fn reset(&mut self) -> term::Result<()> {
+        Ok(queue!(self.0, SetAttribute(Attribute::Reset), ResetColor)?)
+    }
+
+    fn supports_reset(&self) -> bool {
+        true
+    }
+
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn cursor_up(&mut self) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn delete_line(&mut self) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn carriage_return(&mut self) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn get_ref(&self) -> &Vec<u8> {
+        &self.0
+    }
+
+    fn get_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+
+    fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// What a function made to be printed along with its result, when the
+/// result is printed at the top level.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Figure {
+    /// Printed in place of the result when the result is its rows, and
+    /// before the result otherwise.
+    Table(Table),
+    /// Printed in place of the result, `width` columns wide and with
+    /// `height` rows for the curves.
+    Chart {
+        chart: Chart,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl Figure {
+    /// Whether the figure stands for `value`, which then isn't printed.
+    pub fn replaces(&self, value: &Value) -> bool {
+        match self {
+            Figure::Table(table) => table.to_value() == *value,
+            Figure::Chart { .. } => true,
+        }
+    }
+
+    /// The figure as text, tables in `format`, colored if `color` is set.
+    pub fn render(&self, format: TableFormat, color: bool) -> String {
+        match self {
+            Figure::Table(table) => table.render_colored(format, color),
+            Figure::Chart {
+                chart,
+                width,
+                height,
+            } => chart.render(*width, *height, color),
+        }
+    }
+}
+
+/// The format tables are printed in, the last table made, kept for
+/// `save_table`, and the last figure made, with the value it was made for.
+/// Shared by every table function of a context.
+#[derive(Debug)]
+pub struct TableOutput {
+    pub format: TableFormat,
+    pub last: Option<Table>,
+    figure: Option<(Value, Figure)>,
+}
+
+pub type SharedTableOutput = Arc<Mutex<TableOutput>>;
+
+impl Default for TableOutput {
+    fn default() -> Self {
+        Self {
+            format: TableFormat::Terminal,
+            last: None,
+            figure: None,
+        }
+    }
+}
+
+impl TableOutput {
+    pub fn shared() -> SharedTableOutput {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    /// Keeps `table` for `save_table` and as the figure of its rows, and
+    /// gives its rows as a value.
+    pub fn keep(&mut self, table: Table) -> Value {
+        let value = table.to_value();
+        self.keep_for(table, value)
+    }
+
+    /// Keeps `table` for `save_table` and as the figure of `value`, which
+    /// is given back.
+    pub fn keep_for(&mut self, table: Table, value: Value) -> Value {
+        self.figure = Some((value.clone(), Figure::Table(table.clone())));
+        self.last = Some(table);
+        value
+    }
+
+    /// Keeps `chart` as a figure, and gives it drawn without colors as a
+    /// string value, which the figure is for.
+    pub fn keep_chart(&mut self, chart: Chart, width: usize, height: usize) -> Value {
+        let value = Value::String(chart.render(width, height, false));
+        self.figure = Some((
+            value.clone(),
+            Figure::Chart {
+                chart,
+                width,
+                height,
+            },
+        ));
+        value
+    }
+
+    /// Takes the figure kept, rendered if it was made for `value`, the
+    /// result of a top-level expression: tables in the current format, and
+    /// colored if `color` is set. Also tells whether `value` itself should
+    /// be shown after it, which it shouldn't when it is empty or the figure
+    /// stands for it. The figure is forgotten either way, so that it is only
+    /// shown for the expression that made it.
+    pub fn take_figure(&mut self, value: &Value, color: bool) -> (Option<String>, bool) {
+        let figure = self.figure.take().and_then(|(made_for, figure)| {
+            (made_for == *value
+                && !matches!(&figure, Figure::Table(table) if table.rows.is_empty()))
+            .then_some(figure)
+        });
+        let shown = !value.is_empty() && !figure.as_ref().is_some_and(|f| f.replaces(value));
+        (figure.map(|f| f.render(self.format, color)), shown)
+    }
+
+    /// Forgets the figure kept, e.g. when the expression that made it
+    /// failed further on.
+    pub fn forget_figure(&mut self) {
+        self.figure = None;
+    }
+
+    /// Writes `value`, the result of a top-level expression, to `out` along
+    /// with its figure, as [`TableOutput::take_figure`] gives them.
+    pub fn write_result<W: Write>(
+        &mut self,
+        value: &Value,
+        out: &mut W,
+        color: bool,
+    ) -> io::Result<()> {
+        let (figure, shown) = self.take_figure(value, color);
+        if let Some(figure) = figure {
+            write!(out, "{}", figure)?;
+        }
+        if shown {
+            writeln!(out, "{}", value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last table made to `path`.
+    pub fn save(&self, path: &str, format: TableFormat) -> Result<(), String> {
+        let table = self
+            .last
+            .as_ref()
+            .ok_or_else(|| "no table has been made yet".to_string())?;
+        fs::write(path, table.render(format))
+            .map_err(|e| format!("could not write `{}`: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(&["Mana", "fire, \"hot\""]);
+        table.add_row(&["1", "4"]);
+        table.add_row(&["2", "a|b"]);
+        table.highlight_last(1, Highlight::Best);
+        table
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            table().render(TableFormat::Csv),
+            "Mana,\"fire, \"\"hot\"\"\"\n1,4\n2,a|b\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            table().render(TableFormat::Json),
+            "[\n  {\"Mana\": 1, \"fire, \\\"hot\\\"\": 4},\n  {\"Mana\": 2, \"fire, \\\"hot\\\"\": \"a|b\"}\n]\n"
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            table().render(TableFormat::Markdown),
+            "| Mana | fire, \"hot\" |\n|---|---|\n| 1 | 4 |\n| 2 | a\\|b |\n"
+        );
+    }
+
+    #[test]
+    fn html() {
+        let html = table().render(TableFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr><th>Mana</th><th>fire, &quot;hot&quot;</th></tr>"));
+        assert!(html.contains("<tr><td>2</td><td class=\"best\">a|b</td></tr>"));
+    }
+
+    #[test]
+    fn rows_as_values() {
+        let mut table = Table::new(&["Mana", "fire", "ice"]);
+        table.add_row(&["1", "+4", "0.5"]);
+        table.add_row(&["2", "-1", "n/a"]);
+        let value = table.to_value();
+        assert_eq!(
+            value,
+            Value::Tuple(vec![
+                Value::Tuple(vec![Value::Int(1), Value::Int(4), Value::Float(0.5)]),
+                Value::Tuple(vec![
+                    Value::Int(2),
+                    Value::Int(-1),
+                    Value::String("n/a".to_string())
+                ]),
+            ])
+        );
+
+        let mut output = TableOutput::default();
+        assert_eq!(output.keep(table.clone()), value);
+        assert_eq!(
+            output.take_figure(&value, false),
+            (Some(table.render(TableFormat::Terminal)), false)
+        );
+        output.keep(table.clone());
+        assert_eq!(output.take_figure(&Value::Int(1), false), (None, true));
+
+        table.leave_out_of_value(2);
+        assert_eq!(
+            row_of(&table.to_value(), 0),
+            Value::Tuple(vec![Value::Int(1), Value::Int(4)])
+        );
+    }
+
+    fn row_of(rows: &Value, index: usize) -> Value {
+        rows.as_tuple().unwrap()[index].clone()
+    }
+
+    #[test]
+    fn results_with_figures() {
+        let mut output = TableOutput {
+            format: TableFormat::Csv,
+            ..TableOutput::default()
+        };
+        let write = |output: &mut TableOutput, value: &Value| {
+            let mut out = Vec::new();
+            output.write_result(value, &mut out, false).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let rows = output.keep(table());
+        assert_eq!(
+            write(&mut output, &rows),
+            "Mana,\"fire, \"\"hot\"\"\"\n1,4\n2,a|b\n"
+        );
+
+        let best = Value::Tuple(vec![Value::Int(1), Value::Int(4)]);
+        output.keep_for(table(), best.clone());
+        assert!(write(&mut output, &best).ends_with("2,a|b\n(1, 4)\n"));
+        // The figure is only written once, even for an equal value.
+        assert_eq!(write(&mut output, &best), "(1, 4)\n");
+
+        output.keep_for(table(), best.clone());
+        assert_eq!(write(&mut output, &Value::Empty), "");
+        assert_eq!(write(&mut output, &best), "(1, 4)\n");
+
+        output.keep(table());
+        output.forget_figure();
+        assert_eq!(write(&mut output, &rows), "((1, 4), (2, \"a|b\"))\n");
+
+        let mut chart = Chart::new("mana", "damage");
+        chart.add_series("fire", vec![(0, 0), (10, 10)]);
+        let drawn = chart.render(16, 3, false);
+        let value = output.keep_chart(chart, 16, 3);
+        assert_eq!(value, Value::String(drawn.clone()));
+        assert_eq!(write(&mut output, &value), drawn);
+        assert_eq!(write(&mut output, &Value::Int(2)), "2\n");
+    }
+
+    #[test]
+    fn colored_highlights() {
+        let plain = table().render(TableFormat::Terminal);
+        let colored = table().render_colored(TableFormat::Terminal, true);
+        assert_ne!(colored, plain);
+        assert!(colored.contains("\x1b["));
+        // The escape sequences don't count towards the width of the column.
+        assert_eq!(colored.lines().next(), plain.lines().next());
+        assert_eq!(table().render_colored(TableFormat::Terminal, false), plain);
+    }
+
+    #[test]
+    fn formats_from_paths() {
+        assert_eq!(TableFormat::from_path("fire.csv"), Some(TableFormat::Csv));
+        assert_eq!(
+            TableFormat::from_path("wiki/fire.md"),
+            Some(TableFormat::Markdown)
+        );
+        assert_eq!(TableFormat::from_path("fire"), None);
+    }
+}
@@ -0,0 +1,265 @@
+use evalexpr::build_operator_tree;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::functions::{function_names, CALCULATOR_FUNCTIONS};
+use crate::magic::Magic;
+
+/// Functions that evalexpr provides on its own. A magic function with one of
+/// these names would shadow the builtin.
+pub const EVALEXPR_BUILTINS: &[&str] = &[
+    "min",
+    "max",
+    "floor",
+    "round",
+    "ceil",
+    "if",
+    "contains",
+    "contains_any",
+    "len",
+    "typeof",
+    "random",
+    "bitand",
+    "bitor",
+    "bitxor",
+    "bitnot",
+    "shl",
+    "shr",
+    "math::ln",
+    "math::log",
+    "math::log2",
+    "math::log10",
+    "math::exp",
+    "math::exp2",
+    "math::pow",
+    "math::cos",
+    "math::acos",
+    "math::cosh",
+    "math::acosh",
+    "math::sin",
+    "math::asin",
+    "math::sinh",
+    "math::asinh",
+    "math::tan",
+    "math::atan",
+    "math::atan2",
+    "math::tanh",
+    "math::atanh",
+    "math::sqrt",
+    "math::cbrt",
+    "math::hypot",
+    "math::is_nan",
+    "math::is_finite",
+    "math::is_infinite",
+    "math::is_normal",
+    "math::abs",
+    "str::regex_matches",
+    "str::regex_replace",
+    "str::to_lowercase",
+    "str::to_uppercase",
+    "str::trim",
+    "str::from",
+    "str::substring",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// More than one `register` block uses this name.
+    DuplicateName { name: String, count: usize },
+    /// A function generated for `first` has the same name as one generated
+    /// for `second`, e.g. `def_fire` for `fire` and a magic called `def_fire`.
+    FunctionClash {
+        function: String,
+        first: String,
+        second: String,
+    },
+    /// A function generated for `magic` would shadow an evalexpr builtin or
+    /// a calculator function such as `compare`.
+    BuiltinClash { function: String, magic: String },
+    /// The name cannot be called as a function in an expression.
+    InvalidIdentifier { name: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::DuplicateName { name, count } => {
+                write!(f, "magic `{}` is registered {} times", name, count)
+            }
+            ValidationError::FunctionClash {
+                function,
+                first,
+                second,
+            } => write!(
+                f,
+                "function `{}` is generated by both `{}` and `{}`",
+                function, first, second
+            ),
+            ValidationError::BuiltinClash { function, magic } => write!(
+                f,
+                "function `{}` of magic `{}` would hide the builtin of the same name",
+                function, magic
+            ),
+            ValidationError::InvalidIdentifier { name } => {
+                write!(f, "`{}` can't be used as a function name", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Whether `name(...)` parses as a call to a function called `name`.
+fn is_valid_identifier(name: &str) -> bool {
+    match build_operator_tree(&format!("{}()", name)) {
+        Ok(tree) => {
+            tree.iter_identifiers().count() == 1
+                && tree.iter_function_identifiers().next() == Some(name)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Checks the loaded magics before their functions are registered, so that
+/// no function silently replaces another one. Returns every problem found.
+pub fn validate_magics(magics: &[Magic]) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for mag in magics {
+        *counts.entry(&mag.name).or_default() += 1;
+    }
+    let mut reported: Vec<&str> = Vec::new();
+    for mag in magics {
+        let count = counts[mag.name.as_str()];
+        if count > 1 && !reported.contains(&mag.name.as_str()) {
+            reported.push(&mag.name);
+            errors.push(ValidationError::DuplicateName {
+                name: mag.name.clone(),
+                count,
+            });
+        }
+    }
+
+    let mut owners: HashMap<String, &str> = HashMap::new();
+    for mag in magics {
+        if !is_valid_identifier(&mag.name) {
+            errors.push(ValidationError::InvalidIdentifier {
+                name: mag.name.clone(),
+            });
+            continue;
+        }
+
+        for function in function_names(mag) {
+            if EVALEXPR_BUILTINS.contains(&function.as_str())
+                || CALCULATOR_FUNCTIONS.contains(&function.as_str())
+            {
+                errors.push(ValidationError::BuiltinClash {
+                    function: function.clone(),
+                    magic: mag.name.clone(),
+                });
+            }
+            match owners.get(&function) {
+                Some(&first) if first == mag.name => {}
+                Some(&first) => errors.push(ValidationError::FunctionClash {
+                    function,
+                    first: first.to_string(),
+                    second: mag.name.clone(),
+                }),
+                None => {
+                    owners.insert(function, &mag.name);
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_file::parse_file;
+    use crate::rules::Ruleset;
+
+    fn validate(content: &str) -> Result<(), Vec<ValidationError>> {
+        validate_magics(&parse_file(content, "test.rpg", &mut Ruleset::default()).unwrap())
+    }
+
+    #[test]
+    fn distinct_magics_are_valid() {
+        assert_eq!(validate("register fire { } register ice { }"), Ok(()));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let errors = validate(
+            "register fire { }
+             register fire { }
+             register def_fire { }
+             register floor { always_def true }
+             register compare { }
+             register fi-re { }",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::DuplicateName {
+                    name: "fire".to_string(),
+                    count: 2
+                },
+                ValidationError::FunctionClash {
+                    function: "def_fire".to_string(),
+                    first: "fire".to_string(),
+                    second: "def_fire".to_string()
+                },
+                ValidationError::FunctionClash {
+                    function: "t_def_fire".to_string(),
+                    first: "fire".to_string(),
+                    second: "def_fire".to_string()
+                },
+                ValidationError::FunctionClash {
+                    function: "grid_def_fire".to_string(),
+                    first: "fire".to_string(),
+                    second: "def_fire".to_string()
+                },
+                ValidationError::FunctionClash {
+                    function: "need_def_fire".to_string(),
+                    first: "fire".to_string(),
+                    second: "def_fire".to_string()
+                },
+                ValidationError::FunctionClash {
+                    function: "best_def_fire".to_string(),
+                    first: "fire".to_string(),
+                    second: "def_fire".to_string()
+                },
+                ValidationError::BuiltinClash {
+                    function: "floor".to_string(),
+                    magic: "floor".to_string()
+                },
+                ValidationError::BuiltinClash {
+                    function: "compare".to_string(),
+                    magic: "compare".to_string()
+                },
+                ValidationError::InvalidIdentifier {
+                    name: "fi-re".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn generated_prefixes_follow_always_def() {
+        assert_eq!(
+            validate("register def_ice { } register ice { always_def true }"),
+            Ok(())
+        );
+        assert!(validate("register at_ice { } register ice { always_def true }").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Notices when files are modified, by polling their modification times.
+#[derive(Debug)]
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Watcher {
+    pub fn new<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let path = path.into();
+                    let time = modified(&path);
+                    (path, time)
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether any file was modified, created or removed since the last
+    /// call, or since the watcher was made.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, time) in &mut self.files {
+            let now = modified(path);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    #[test]
+    fn notices_modified_files() {
+        let path = std::env::temp_dir().join(format!("rpgcalc-watch-{}.rpg", std::process::id()));
+        fs::write(&path, "register fire {}").unwrap();
+
+        let mut watcher = Watcher::new([&path]);
+        assert!(!watcher.changed());
+
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+    }
+}